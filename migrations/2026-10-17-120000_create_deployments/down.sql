@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE files
+    DROP COLUMN deployment_id;
+ALTER TABLE sites
+    DROP COLUMN active_deployment_id;
+DROP TABLE deployments;
@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE deployments (
+    id VARCHAR(255) PRIMARY KEY NOT NULL,
+    site_id VARCHAR(255) NOT NULL,
+    status VARCHAR(255) NOT NULL,
+    file_count BIGINT NOT NULL DEFAULT 0,
+    total_size BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (site_id) REFERENCES sites(id)
+);
+
+-- sites created before deployments keep their files under `sites/{id}/`
+-- and have no active deployment until their next update
+ALTER TABLE sites
+    ADD COLUMN active_deployment_id VARCHAR(255);
+
+ALTER TABLE files
+    ADD COLUMN deployment_id VARCHAR(255) REFERENCES deployments(id);
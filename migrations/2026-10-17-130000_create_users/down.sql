@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sites
+    DROP COLUMN owner_id;
+DROP TABLE api_keys;
+DROP TABLE users;
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+CREATE TABLE users (
+    id VARCHAR(255) PRIMARY KEY NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
+
+CREATE TABLE api_keys (
+    id VARCHAR(255) PRIMARY KEY NOT NULL,
+    user_id VARCHAR(255) NOT NULL,
+    -- sha256 of the token, the token itself is only shown once
+    token_hash VARCHAR(255) NOT NULL UNIQUE,
+    scope VARCHAR(255) NOT NULL DEFAULT 'user',
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- sites created before users only show up for admin keys
+ALTER TABLE sites
+    ADD COLUMN owner_id VARCHAR(255) REFERENCES users(id);
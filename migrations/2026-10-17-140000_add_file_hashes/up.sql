@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- hex encoded sha256 of the file content, used to skip unchanged files on deploy
+ALTER TABLE files
+    ADD COLUMN hash VARCHAR(64);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE domains (
+    id VARCHAR(255) PRIMARY KEY NOT NULL,
+    site_id VARCHAR(255) NOT NULL,
+    host VARCHAR(255) NOT NULL UNIQUE,
+    -- expected in the `_nanohost.{host}` TXT record
+    verification_token VARCHAR(255) NOT NULL,
+    status VARCHAR(255) NOT NULL DEFAULT 'pending',
+    verified_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (site_id) REFERENCES sites(id)
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE files
+    DROP COLUMN index_of;
+ALTER TABLE deployments
+    DROP COLUMN index_file;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE deployments
+    ADD COLUMN index_file VARCHAR(255);
+
+-- directory a file is the index of, '' for the root index
+ALTER TABLE files
+    ADD COLUMN index_of VARCHAR(255);
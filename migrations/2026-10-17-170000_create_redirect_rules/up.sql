@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE redirect_rules (
+    id VARCHAR(255) PRIMARY KEY NOT NULL,
+    site_id VARCHAR(255) NOT NULL,
+    deployment_id VARCHAR(255) NOT NULL,
+    -- rules are matched in the order they were declared
+    position INTEGER NOT NULL,
+    from_path TEXT NOT NULL,
+    to_path TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    force BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (site_id) REFERENCES sites(id),
+    FOREIGN KEY (deployment_id) REFERENCES deployments(id)
+);
+
+CREATE INDEX redirect_rules_deployment_id ON redirect_rules (deployment_id);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE header_rules (
+    id VARCHAR(255) PRIMARY KEY NOT NULL,
+    site_id VARCHAR(255) NOT NULL,
+    deployment_id VARCHAR(255) NOT NULL,
+    -- a header set by several matching rules takes the value of the last one
+    position INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    value TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (site_id) REFERENCES sites(id),
+    FOREIGN KEY (deployment_id) REFERENCES deployments(id)
+);
+
+CREATE INDEX header_rules_deployment_id ON header_rules (deployment_id);
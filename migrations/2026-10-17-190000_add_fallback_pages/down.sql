@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sites
+    DROP COLUMN not_found_file;
+ALTER TABLE sites
+    DROP COLUMN spa;
+ALTER TABLE deployments
+    DROP COLUMN not_found_file;
+ALTER TABLE deployments
+    DROP COLUMN spa;
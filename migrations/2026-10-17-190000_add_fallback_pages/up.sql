@@ -0,0 +1,13 @@
+-- Your SQL goes here
+-- unknown paths serve the index file with a 200 when spa is set,
+-- else not_found_file with a 404
+ALTER TABLE deployments
+    ADD COLUMN spa BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE deployments
+    ADD COLUMN not_found_file VARCHAR(255);
+
+-- follow the active deployment, like index_file
+ALTER TABLE sites
+    ADD COLUMN spa BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE sites
+    ADD COLUMN not_found_file VARCHAR(255);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE files
+    DROP COLUMN gzip_size;
+ALTER TABLE files
+    DROP COLUMN brotli_size;
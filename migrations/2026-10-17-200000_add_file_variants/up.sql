@@ -0,0 +1,7 @@
+-- Your SQL goes here
+-- sizes of the precompressed variants stored next to the file under
+-- `{path}.br` and `{path}.gz`, NULL when the variant wasn't generated
+ALTER TABLE files
+    ADD COLUMN brotli_size BIGINT;
+ALTER TABLE files
+    ADD COLUMN gzip_size BIGINT;
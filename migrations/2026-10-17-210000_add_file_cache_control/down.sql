@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE files
+    DROP COLUMN cache_control;
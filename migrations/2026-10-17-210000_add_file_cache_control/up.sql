@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- resolved when deploying from the name of the file and the cache rules
+-- of the deployment, NULL when no policy applies
+ALTER TABLE files
+    ADD COLUMN cache_control TEXT;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- hosts were only checked before inserting, so concurrent requests could
+-- both claim one. Fails if duplicates already exist, they have to be
+-- resolved by hand first.
+CREATE UNIQUE INDEX sites_host ON sites (host);
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- previous hosts of renamed sites, redirected to the current host of the
+-- site until they expire
+CREATE TABLE host_redirects (
+    id VARCHAR(255) PRIMARY KEY NOT NULL,
+    site_id VARCHAR(255) NOT NULL,
+    host VARCHAR(255) NOT NULL UNIQUE,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (site_id) REFERENCES sites(id)
+);
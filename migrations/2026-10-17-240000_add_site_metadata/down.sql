@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sites
+    DROP COLUMN tags;
+ALTER TABLE sites
+    DROP COLUMN description;
+ALTER TABLE sites
+    DROP COLUMN title;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- descriptive only, set with PATCH /sites/{id}; tags are comma separated
+ALTER TABLE sites
+    ADD COLUMN title VARCHAR(255);
+ALTER TABLE sites
+    ADD COLUMN description TEXT;
+ALTER TABLE sites
+    ADD COLUMN tags TEXT NOT NULL DEFAULT '';
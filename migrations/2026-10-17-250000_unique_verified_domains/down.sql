@@ -0,0 +1,21 @@
+-- This file should undo anything in `up.sql`
+-- only one domain is kept per host, the verified one if any
+CREATE TABLE domains_unique_host (
+    id VARCHAR(255) PRIMARY KEY NOT NULL,
+    site_id VARCHAR(255) NOT NULL,
+    host VARCHAR(255) NOT NULL UNIQUE,
+    -- expected in the `_nanohost.{host}` TXT record
+    verification_token VARCHAR(255) NOT NULL,
+    status VARCHAR(255) NOT NULL DEFAULT 'pending',
+    verified_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (site_id) REFERENCES sites(id)
+);
+INSERT OR IGNORE INTO domains_unique_host
+    SELECT id, site_id, host, verification_token, status, verified_at, created_at, updated_at
+    FROM domains
+    ORDER BY status = 'verified' DESC, created_at;
+DROP TABLE domains;
+ALTER TABLE domains_unique_host
+    RENAME TO domains;
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+-- pending domains no longer claim their host, so several sites can wait for
+-- the same one: only the first verified domain of a host is kept unique.
+CREATE TABLE domains_unique_verified (
+    id VARCHAR(255) PRIMARY KEY NOT NULL,
+    site_id VARCHAR(255) NOT NULL,
+    host VARCHAR(255) NOT NULL,
+    -- expected in the `_nanohost.{host}` TXT record
+    verification_token VARCHAR(255) NOT NULL,
+    status VARCHAR(255) NOT NULL DEFAULT 'pending',
+    verified_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (site_id) REFERENCES sites(id)
+);
+INSERT INTO domains_unique_verified
+    SELECT id, site_id, host, verification_token, status, verified_at, created_at, updated_at
+    FROM domains;
+DROP TABLE domains;
+ALTER TABLE domains_unique_verified
+    RENAME TO domains;
+CREATE UNIQUE INDEX domains_verified_host ON domains (host) WHERE status = 'verified';
@@ -0,0 +1,190 @@
+use std::path::Path;
+
+use reqwest::multipart::{Form, Part};
+use reqwest::{Body, RequestBuilder};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio_util::io::ReaderStream;
+
+use crate::Result;
+
+#[derive(Deserialize)]
+pub struct Site {
+    pub id: String,
+    pub host: String,
+    pub index_file: Option<String>,
+    pub spa: bool,
+    pub not_found_file: Option<String>,
+    pub active_deployment_id: Option<String>,
+    pub updated_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct SiteFile {
+    pub name: String,
+    pub size: i64,
+}
+
+#[derive(Deserialize)]
+pub struct Deployment {
+    pub id: String,
+    pub status: String,
+}
+
+/// Site fields sent along with a zipped deployment.
+pub struct SiteUpload<'a> {
+    pub domain: &'a str,
+    pub suffix: &'a str,
+    pub index_file: Option<&'a str>,
+    pub spa: Option<bool>,
+    pub not_found_file: Option<&'a str>,
+    /// Zip file streamed to the API
+    pub archive: &'a Path,
+}
+
+impl SiteUpload<'_> {
+    async fn into_form(self) -> Result<Form> {
+        let file = tokio::fs::File::open(self.archive).await?;
+        let size = file.metadata().await?.len();
+        let archive = Part::stream_with_length(Body::wrap_stream(ReaderStream::new(file)), size)
+            .file_name("site.zip")
+            .mime_str("application/zip")?;
+
+        let mut form = Form::new()
+            .text("domain", self.domain.to_string())
+            .text("suffix", self.suffix.to_string())
+            .text("site_type", "zip");
+        if let Some(index_file) = self.index_file {
+            form = form.text("index_file", index_file.to_string());
+        }
+        if let Some(spa) = self.spa {
+            form = form.text("spa", spa.to_string());
+        }
+        if let Some(not_found_file) = self.not_found_file {
+            form = form.text("not_found_file", not_found_file.to_string());
+        }
+
+        Ok(form.part("file", archive))
+    }
+}
+
+/// Client of the nanohost HTTP API.
+pub struct Client {
+    http: reqwest::Client,
+    api_url: String,
+    api_key: String,
+}
+
+impl Client {
+    pub fn new(api_url: &str, api_key: &str) -> Client {
+        Client {
+            http: reqwest::Client::new(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.api_url, path)
+    }
+
+    /// Sends an authenticated request, error responses of the API are
+    /// returned as errors with their message.
+    async fn send(&self, request: RequestBuilder) -> Result<Value> {
+        let response = request.bearer_auth(&self.api_key).send().await?;
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+
+        if !status.is_success() {
+            let message = body["message"].as_str().unwrap_or("Request failed");
+            return Err(format!("{} ({})", message, status).into());
+        }
+
+        Ok(body)
+    }
+
+    /// Lists every site, following the pages of the API.
+    pub async fn list_sites(&self) -> Result<Vec<Site>> {
+        let mut sites: Vec<Site> = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut query = vec![("limit", "200".to_string())];
+            if let Some(cursor) = cursor {
+                query.push(("cursor", cursor));
+            }
+
+            let mut body = self
+                .send(self.http.get(self.url("/sites")).query(&query))
+                .await?;
+            let page: Vec<Site> = serde_json::from_value(body["sites"].take())?;
+            sites.extend(page);
+
+            cursor = body["next_cursor"].as_str().map(str::to_string);
+            if cursor.is_none() {
+                return Ok(sites);
+            }
+        }
+    }
+
+    pub async fn get_site(&self, site_id: &str) -> Result<(Site, Vec<SiteFile>)> {
+        let mut body = self
+            .send(self.http.get(self.url(&format!("/sites/{}", site_id))))
+            .await?;
+        let site = serde_json::from_value(body["site"].take())?;
+        let files = serde_json::from_value(body["files"].take())?;
+        Ok((site, files))
+    }
+
+    /// Creates a site from a zipped directory and returns its id.
+    pub async fn create_site(&self, upload: SiteUpload<'_>) -> Result<String> {
+        let body = self
+            .send(
+                self.http
+                    .post(self.url("/sites"))
+                    .multipart(upload.into_form().await?),
+            )
+            .await?;
+        Ok(body["site_id"].as_str().unwrap_or_default().to_string())
+    }
+
+    /// Deploys a zipped directory to a site and returns the deployment id.
+    pub async fn update_site(&self, site_id: &str, upload: SiteUpload<'_>) -> Result<String> {
+        let body = self
+            .send(
+                self.http
+                    .put(self.url(&format!("/sites/{}", site_id)))
+                    .multipart(upload.into_form().await?),
+            )
+            .await?;
+        Ok(body["deployment_id"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    pub async fn delete_site(&self, site_id: &str) -> Result<()> {
+        self.send(self.http.delete(self.url(&format!("/sites/{}", site_id))))
+            .await?;
+        Ok(())
+    }
+
+    /// Deployments of the site, most recent first.
+    pub async fn list_deployments(&self, site_id: &str) -> Result<Vec<Deployment>> {
+        let mut body = self
+            .send(
+                self.http
+                    .get(self.url(&format!("/sites/{}/deployments", site_id))),
+            )
+            .await?;
+        Ok(serde_json::from_value(body["deployments"].take())?)
+    }
+
+    pub async fn activate_deployment(&self, site_id: &str, deployment_id: &str) -> Result<()> {
+        self.send(self.http.post(self.url(&format!(
+            "/sites/{}/deployments/{}/activate",
+            site_id, deployment_id
+        ))))
+        .await?;
+        Ok(())
+    }
+}
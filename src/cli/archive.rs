@@ -0,0 +1,78 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use tempfile::NamedTempFile;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::config::CONFIG_FILE;
+use crate::Result;
+
+/// Version control and OS files which are never part of a site.
+const EXCLUDED_NAMES: [&str; 6] = [
+    ".git",
+    ".hg",
+    ".svn",
+    ".DS_Store",
+    "Thumbs.db",
+    "desktop.ini",
+];
+
+/// Whether a file or directory is left out of the archive. `.env` files and
+/// the project config are excluded as well since they can hold secrets,
+/// other dot files (e.g. `.well-known/`) are deployed.
+fn is_excluded(file_name: &str) -> bool {
+    EXCLUDED_NAMES.contains(&file_name) || file_name.starts_with(".env") || file_name == CONFIG_FILE
+}
+
+/// Zips every file of `dir` into a temporary file, paths inside the archive
+/// are relative to `dir`. The file is removed once dropped.
+pub fn zip_directory(dir: &Path) -> Result<NamedTempFile> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()).into());
+    }
+
+    let archive = NamedTempFile::new()?;
+    let mut writer = ZipWriter::new(archive.reopen()?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let file_count = add_directory(&mut writer, dir, "", options)?;
+    if file_count == 0 {
+        return Err(format!("{} has no files to deploy", dir.display()).into());
+    }
+
+    writer.finish()?;
+    Ok(archive)
+}
+
+fn add_directory(
+    writer: &mut ZipWriter<File>,
+    dir: &Path,
+    prefix: &str,
+    options: SimpleFileOptions,
+) -> Result<usize> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut file_count = 0;
+    for entry in entries {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if is_excluded(&file_name) {
+            continue;
+        }
+
+        let name = format!("{}{}", prefix, file_name);
+        let path = entry.path();
+        if path.is_dir() {
+            file_count += add_directory(writer, &path, &format!("{}/", name), options)?;
+        } else {
+            writer.start_file(name, options)?;
+            io::copy(&mut File::open(&path)?, writer)?;
+            file_count += 1;
+        }
+    }
+
+    Ok(file_count)
+}
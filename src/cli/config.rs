@@ -0,0 +1,67 @@
+use std::{env, fs, io, path::Path};
+
+use serde::Deserialize;
+
+use crate::Result;
+
+pub const CONFIG_FILE: &str = "nanohost.toml";
+
+fn default_api_url() -> String {
+    "http://127.0.0.1:8080".to_string()
+}
+
+/// Project settings read from `nanohost.toml`. The API URL and key can be
+/// overridden with `NANOHOST_API_URL` and `NANOHOST_API_KEY`, so the key
+/// doesn't have to be committed along with the project.
+#[derive(Deserialize)]
+pub struct ProjectConfig {
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    pub api_key: Option<String>,
+
+    pub domain: Option<String>,
+    pub suffix: Option<String>,
+    /// Detected by the API when not set
+    pub index_file: Option<String>,
+    /// Serve the index file for unknown paths (single-page apps)
+    pub spa: Option<bool>,
+    /// Page served with a 404 for unknown paths, `404.html` is detected by the API
+    pub not_found_file: Option<String>,
+}
+
+impl ProjectConfig {
+    /// Reads the config file, every setting falls back to its default
+    /// when the file doesn't exist.
+    pub fn load(path: &Path) -> Result<ProjectConfig> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e).into()),
+        };
+
+        let mut config: ProjectConfig =
+            toml::from_str(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+
+        if let Ok(api_url) = env::var("NANOHOST_API_URL") {
+            config.api_url = api_url;
+        }
+        if let Ok(api_key) = env::var("NANOHOST_API_KEY") {
+            config.api_key = Some(api_key);
+        }
+
+        Ok(config)
+    }
+
+    /// Host of the project's site, normalized the same way as the API does:
+    /// a dot between the name and the suffix, lowercase and punycode.
+    pub fn host(&self) -> Option<String> {
+        let (Some(domain), Some(suffix)) = (&self.domain, &self.suffix) else {
+            return None;
+        };
+
+        let name = domain.trim();
+        let suffix = suffix.trim().trim_start_matches('.').trim_end_matches('.');
+        let host = format!("{}.{}", name, suffix);
+        Some(idna::domain_to_ascii(&host).unwrap_or(host))
+    }
+}
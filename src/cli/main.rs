@@ -0,0 +1,218 @@
+mod api;
+mod archive;
+mod config;
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::api::{Site, SiteUpload};
+use crate::config::{ProjectConfig, CONFIG_FILE};
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Deploys static sites to nanohost.
+#[derive(Parser)]
+#[command(name = "nanohost", version)]
+struct Cli {
+    /// Project config file
+    #[arg(long, global = true, default_value = CONFIG_FILE)]
+    config: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Zips a directory and deploys it, the site is created on the first deploy
+    Deploy { dir: PathBuf },
+    /// Lists your sites
+    List,
+    /// Shows a site and the files of its active deployment
+    Info {
+        /// Site id or host
+        site: String,
+    },
+    /// Deletes a site along with all of its deployments
+    Delete {
+        /// Site id or host
+        site: String,
+    },
+    /// Activates the previous deployment of the project's site
+    Rollback {
+        /// Site id or host, defaults to the site of the project
+        #[arg(long)]
+        site: Option<String>,
+        /// Deployment to activate instead of the previous one
+        #[arg(long)]
+        to: Option<String>,
+    },
+}
+
+/// Finds a site by id or host, or the site of the project when none is given.
+async fn find_site(
+    client: &api::Client,
+    config: &ProjectConfig,
+    site: Option<&str>,
+) -> Result<Site> {
+    let site = match site {
+        Some(site) => site.to_string(),
+        None => config
+            .host()
+            .ok_or_else(|| format!("No site given and no domain and suffix in {}", CONFIG_FILE))?,
+    };
+
+    client
+        .list_sites()
+        .await?
+        .into_iter()
+        .find(|candidate| candidate.id == site || candidate.host == site)
+        .ok_or_else(|| format!("Site {} not found", site).into())
+}
+
+async fn deploy(client: &api::Client, config: &ProjectConfig, dir: PathBuf) -> Result<()> {
+    let (Some(domain), Some(suffix), Some(host)) = (&config.domain, &config.suffix, config.host())
+    else {
+        return Err(format!("Set the domain and suffix of the site in {}", CONFIG_FILE).into());
+    };
+
+    let archive = archive::zip_directory(&dir)?;
+    println!(
+        "Uploading {} ({} bytes)",
+        dir.display(),
+        archive.as_file().metadata()?.len()
+    );
+
+    let upload = SiteUpload {
+        domain,
+        suffix,
+        index_file: config.index_file.as_deref(),
+        spa: config.spa,
+        not_found_file: config.not_found_file.as_deref(),
+        archive: archive.path(),
+    };
+
+    let existing_site = client
+        .list_sites()
+        .await?
+        .into_iter()
+        .find(|site| site.host == host);
+
+    match existing_site {
+        Some(site) => {
+            let deployment_id = client.update_site(&site.id, upload).await?;
+            println!("Deployed {} (deployment {})", site.id, deployment_id);
+        }
+        None => {
+            let site_id = client.create_site(upload).await?;
+            println!("Created site {}", site_id);
+        }
+    }
+
+    println!("https://{}", host);
+    Ok(())
+}
+
+async fn list(client: &api::Client) -> Result<()> {
+    let sites = client.list_sites().await?;
+    if sites.is_empty() {
+        println!("No sites yet, deploy one with `nanohost deploy <dir>`");
+        return Ok(());
+    }
+
+    for site in sites {
+        println!("{}  {}  {}", site.id, site.host, site.updated_at);
+    }
+    Ok(())
+}
+
+async fn info(client: &api::Client, config: &ProjectConfig, site: &str) -> Result<()> {
+    let site = find_site(client, config, Some(site)).await?;
+    let (site, files) = client.get_site(&site.id).await?;
+
+    println!("id:         {}", site.id);
+    println!("url:        https://{}", site.host);
+    println!("index file: {}", site.index_file.unwrap_or_default());
+    println!("spa:        {}", site.spa);
+    if let Some(not_found_file) = site.not_found_file {
+        println!("404 page:   {}", not_found_file);
+    }
+    println!(
+        "deployment: {}",
+        site.active_deployment_id.unwrap_or_default()
+    );
+    println!("files:");
+    for file in files {
+        println!("  {}  ({} bytes)", file.name, file.size);
+    }
+    Ok(())
+}
+
+async fn delete(client: &api::Client, config: &ProjectConfig, site: &str) -> Result<()> {
+    let site = find_site(client, config, Some(site)).await?;
+    client.delete_site(&site.id).await?;
+
+    println!("Deleted {}", site.host);
+    Ok(())
+}
+
+async fn rollback(
+    client: &api::Client,
+    config: &ProjectConfig,
+    site: Option<&str>,
+    to: Option<String>,
+) -> Result<()> {
+    let site = find_site(client, config, site).await?;
+
+    let deployment_id = match to {
+        Some(deployment_id) => deployment_id,
+        None => {
+            // Deployments are listed most recent first, the previous one is
+            // the first inactive deployment after the active one
+            let deployments = client.list_deployments(&site.id).await?;
+            let active_index = deployments
+                .iter()
+                .position(|deployment| Some(&deployment.id) == site.active_deployment_id.as_ref());
+            deployments
+                .into_iter()
+                .skip(active_index.map_or(0, |index| index + 1))
+                .find(|deployment| deployment.status == "inactive")
+                .map(|deployment| deployment.id)
+                .ok_or("No previous deployment to roll back to")?
+        }
+    };
+
+    client.activate_deployment(&site.id, &deployment_id).await?;
+
+    println!("Rolled back {} to deployment {}", site.host, deployment_id);
+    println!("https://{}", site.host);
+    Ok(())
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let config = ProjectConfig::load(&cli.config)?;
+    let api_key = config.api_key.clone().ok_or_else(|| {
+        format!(
+            "Set api_key in {} or the NANOHOST_API_KEY environment variable",
+            CONFIG_FILE
+        )
+    })?;
+    let client = api::Client::new(&config.api_url, &api_key);
+
+    match cli.command {
+        Command::Deploy { dir } => deploy(&client, &config, dir).await,
+        Command::List => list(&client).await,
+        Command::Info { site } => info(&client, &config, &site).await,
+        Command::Delete { site } => delete(&client, &config, &site).await,
+        Command::Rollback { site, to } => rollback(&client, &config, site.as_deref(), to).await,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run(Cli::parse()).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -5,14 +5,24 @@ use std::env;
 #[derive(Deserialize)]
 pub struct Config {
     pub app_url: String,
-    pub service_port: String,
-    pub database_url: String,
-    pub cors_domains: Vec<String>,
-    pub is_development: bool,
+    pub serve_sites: bool,
+    pub admin_api_key: Option<String>,
+    pub domain_verification_interval: usize,
+    pub host_redirect_cleanup_interval: usize,
+
+    pub html_allowed_types: Vec<String>,
+    pub html_denied_types: Vec<String>,
+    pub archive_allowed_types: Vec<String>,
+    pub archive_denied_types: Vec<String>,
+
+    pub site_suffixes: Vec<String>,
+    pub reserved_names: Vec<String>,
+    pub blocked_words: Vec<String>,
+
+    pub storage_backend: String,
+    pub local_storage_path: String,
 
     pub aws_region: String,
-    pub aws_access_key_id: String,
-    pub aws_secret_access_key: String,
 
     pub aws_s3_bucket_name: String,
 
@@ -28,14 +38,39 @@ impl Config {
 
         Config {
             app_url: Self::get_env("APP_URL", "127.0.0.1:8080"),
-            service_port: Self::get_env("SERVICE_PORT", "5775"),
-            database_url: Self::get_env("DATABASE_URL", "/data/db.sqlite"),
-            cors_domains: Self::get_env_list("CORS_DOMAINS", ""),
-            is_development: Self::get_env_bool("IS_DEVELOPMENT", false),
+            serve_sites: Self::get_env_bool("SERVE_SITES", false),
+            admin_api_key: Self::get_env_optional("ADMIN_API_KEY"),
+            domain_verification_interval: Self::get_env_interval(
+                "DOMAIN_VERIFICATION_INTERVAL",
+                60,
+            ),
+            host_redirect_cleanup_interval: Self::get_env_interval(
+                "HOST_REDIRECT_CLEANUP_INTERVAL",
+                3600,
+            ),
+
+            html_allowed_types: Self::get_env_list(
+                "HTML_ALLOWED_TYPES",
+                "text/*,application/javascript,application/json,image/*,font/*",
+            ),
+            html_denied_types: Self::get_env_list("HTML_DENIED_TYPES", ""),
+            archive_allowed_types: Self::get_env_list("ARCHIVE_ALLOWED_TYPES", ""),
+            archive_denied_types: Self::get_env_list(
+                "ARCHIVE_DENIED_TYPES",
+                "application/x-msdownload,application/x-sh,application/x-executable",
+            ),
+
+            site_suffixes: Self::get_env_required_list("SITE_SUFFIXES", ".nanohost.dev"),
+            reserved_names: Self::get_env_list(
+                "RESERVED_NAMES",
+                "admin,api,app,assets,auth,cdn,dashboard,ftp,imap,login,mail,ns1,ns2,pop,root,smtp,static,status,www",
+            ),
+            blocked_words: Self::get_env_list("BLOCKED_WORDS", ""),
+
+            storage_backend: Self::get_env_choice("STORAGE_BACKEND", "s3", &["s3", "local"]),
+            local_storage_path: Self::get_env("LOCAL_STORAGE_PATH", "data"),
 
             aws_region: Self::get_env("AWS_REGION", "us-east-1"),
-            aws_access_key_id: Self::get_env("AWS_ACCESS_KEY_ID", ""),
-            aws_secret_access_key: Self::get_env("AWS_SECRET_ACCESS_KEY", ""),
 
             aws_s3_bucket_name: Self::get_env("AWS_S3_BUCKET_NAME", ""),
             aws_cloudfront_kvs_arn: Self::get_env("AWS_CLOUDFRONT_KVS_ARN", ""),
@@ -47,6 +82,20 @@ impl Config {
         env::var(key).unwrap_or_else(|_| default.to_string())
     }
 
+    /// A value which must be one of `choices`, so a typo doesn't silently
+    /// fall back to another behavior.
+    fn get_env_choice(key: &str, default: &str, choices: &[&str]) -> String {
+        let value = Self::get_env(key, default);
+        if !choices.contains(&value.as_str()) {
+            panic!("{} must be one of {}", key, choices.join(", "));
+        }
+        value
+    }
+
+    fn get_env_optional(key: &str) -> Option<String> {
+        env::var(key).ok().filter(|value| !value.is_empty())
+    }
+
     fn get_env_list(key: &str, default: &str) -> Vec<String> {
         env::var(key)
             .unwrap_or_else(|_| default.to_string())
@@ -56,17 +105,35 @@ impl Config {
             .collect()
     }
 
+    /// A list which can't be empty, e.g. an allowlist.
+    fn get_env_required_list(key: &str, default: &str) -> Vec<String> {
+        let list = Self::get_env_list(key, default);
+        if list.is_empty() {
+            panic!("{} must list at least one value", key);
+        }
+        list
+    }
+
     fn get_env_usize(key: &str, default: usize) -> usize {
         env::var(key)
             .unwrap_or_else(|_| default.to_string())
             .parse()
-            .expect(&format!("Failed to parse {}", key))
+            .unwrap_or_else(|_| panic!("Failed to parse {}", key))
+    }
+
+    /// Seconds between two runs of a worker, which can't be 0.
+    fn get_env_interval(key: &str, default: usize) -> usize {
+        let interval = Self::get_env_usize(key, default);
+        if interval == 0 {
+            panic!("{} must be greater than 0", key);
+        }
+        interval
     }
 
     fn get_env_bool(key: &str, default: bool) -> bool {
         env::var(key)
             .unwrap_or_else(|_| default.to_string())
             .parse()
-            .expect(&format!("Failed to parse {}", key))
+            .unwrap_or_else(|_| panic!("Failed to parse {}", key))
     }
 }
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use diesel::result::DatabaseErrorKind;
+use serde_json::json;
+
+/// Error returned by handlers and services.
+///
+/// Every variant maps to an HTTP status and a stable `code` so clients
+/// can handle failures without parsing the message.
+#[derive(Debug)]
+pub enum AppError {
+    /// The request is invalid, e.g. an unsupported site type or file
+    Validation(String),
+    /// The uploaded archive can't be read
+    InvalidArchive(String),
+    Unauthorized,
+    Forbidden(String),
+    NotFound(String),
+    /// The request conflicts with the current state, e.g. a host already claimed
+    Conflict(String),
+    Database(String),
+    /// The storage backend (S3 or local disk) failed
+    Storage(String),
+    /// The DynamoDB routing table failed
+    Routing(String),
+    /// The CloudFront key value store failed
+    EdgeConfig(String),
+    /// Looking up the DNS records of a custom domain failed
+    Dns(String),
+    Internal(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Validation(_) => "validation_error",
+            AppError::InvalidArchive(_) => "invalid_archive",
+            AppError::Unauthorized => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::Database(_) => "database_error",
+            AppError::Storage(_) => "storage_error",
+            AppError::Routing(_) => "routing_error",
+            AppError::EdgeConfig(_) => "edge_config_error",
+            AppError::Dns(_) => "dns_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// Message sent to the client, details of server errors are only logged.
+    fn public_message(&self) -> String {
+        match self {
+            AppError::Database(_) => "A database error occurred".to_string(),
+            AppError::Storage(_) => "Failed to access the site files".to_string(),
+            AppError::Routing(_) => "Failed to update the site routing".to_string(),
+            AppError::EdgeConfig(_) => "Failed to update the edge configuration".to_string(),
+            AppError::Dns(_) => "Failed to look up the domain records".to_string(),
+            AppError::Internal(_) => "An internal error occurred".to_string(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Unauthorized => write!(f, "Missing or invalid API key"),
+            AppError::Validation(message)
+            | AppError::InvalidArchive(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::Database(message)
+            | AppError::Storage(message)
+            | AppError::Routing(message)
+            | AppError::EdgeConfig(message)
+            | AppError::Dns(message)
+            | AppError::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Validation(_) | AppError::InvalidArchive(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Storage(_)
+            | AppError::Routing(_)
+            | AppError::EdgeConfig(_)
+            | AppError::Dns(_) => StatusCode::BAD_GATEWAY,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.status_code().is_server_error() {
+            println!("Error ({}): {}", self.code(), self);
+        }
+
+        HttpResponse::build(self.status_code()).json(json!({
+            "code": self.code(),
+            "message": self.public_message(),
+        }))
+    }
+}
+
+impl From<diesel::result::Error> for AppError {
+    fn from(e: diesel::result::Error) -> Self {
+        match e {
+            diesel::result::Error::NotFound => AppError::NotFound("Not found".to_string()),
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                AppError::Conflict("Already exists".to_string())
+            }
+            e => AppError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for AppError {
+    fn from(e: diesel::r2d2::PoolError) -> Self {
+        AppError::Database(format!("couldn't get db connection from pool: {}", e))
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
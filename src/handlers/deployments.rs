@@ -0,0 +1,1020 @@
+use std::collections::HashMap;
+
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::handlers::domains::site_hosts;
+use crate::handlers::sites::find_site;
+use crate::middleware::auth::Caller;
+use crate::models::{Deployment, DeploymentStatus, File, HeaderRule, RedirectRule, Site};
+use crate::services::storage::{ObjectHeaders, Storage};
+use crate::services::{cloudfront_key_value, dynamodb};
+use crate::utils::cache_control::cache_control_for;
+use crate::utils::compression::{is_compressible, Encoding};
+use crate::utils::deployment_config::DeploymentConfig;
+use crate::utils::upload_file::UploadedFile;
+use crate::utils::{headers, redirects};
+use actix_multipart::form::tempfile::TempFile;
+use actix_web::{web, HttpResponse};
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+
+/// A file the client wants in its next deployment.
+#[derive(Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub hash: String,
+    pub size: i64,
+}
+
+/// Every file of a deployment, sent by clients to only upload changed files.
+#[derive(Deserialize)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+}
+
+/// Builds the DynamoDB routing item the edge uses to serve `host`
+/// from the files of `deployment`.
+pub fn routing_item(host: &str, deployment: &Deployment) -> HashMap<String, AttributeValue> {
+    let mut dynamodb_values: HashMap<String, AttributeValue> = HashMap::new();
+    dynamodb_values.insert("host".to_string(), AttributeValue::S(host.to_string()));
+    dynamodb_values.insert(
+        "siteId".to_string(),
+        AttributeValue::S(deployment.site_id.clone()),
+    );
+    dynamodb_values.insert(
+        "deploymentId".to_string(),
+        AttributeValue::S(deployment.id.clone()),
+    );
+    dynamodb_values.insert("prefix".to_string(), AttributeValue::S(deployment.path()));
+    if let Some(index_file) = &deployment.index_file {
+        dynamodb_values.insert(
+            "indexFile".to_string(),
+            AttributeValue::S(index_file.clone()),
+        );
+    }
+    dynamodb_values.insert("spa".to_string(), AttributeValue::Bool(deployment.spa));
+    if let Some(not_found_file) = &deployment.not_found_file {
+        dynamodb_values.insert(
+            "notFoundFile".to_string(),
+            AttributeValue::S(not_found_file.clone()),
+        );
+    }
+    dynamodb_values.insert(
+        "cacheKey".to_string(),
+        AttributeValue::S(format!(
+            "{}=x={}",
+            deployment.site_id.clone(),
+            Utc::now().timestamp()
+        )),
+    );
+    dynamodb_values.insert(
+        "timestamp".to_string(),
+        AttributeValue::N(Utc::now().timestamp().to_string()),
+    );
+
+    dynamodb_values
+}
+
+/// Builds the routing item of a previous host of a site, which the edge
+/// redirects to `target_host` until `expires_at`. `expiresAt` can be used
+/// as the TTL attribute of the table.
+pub fn redirect_item(
+    host: &str,
+    site_id: &str,
+    target_host: &str,
+    expires_at: NaiveDateTime,
+) -> HashMap<String, AttributeValue> {
+    let mut dynamodb_values: HashMap<String, AttributeValue> = HashMap::new();
+    dynamodb_values.insert("host".to_string(), AttributeValue::S(host.to_string()));
+    dynamodb_values.insert("siteId".to_string(), AttributeValue::S(site_id.to_string()));
+    dynamodb_values.insert(
+        "redirectTo".to_string(),
+        AttributeValue::S(target_host.to_string()),
+    );
+    dynamodb_values.insert(
+        "expiresAt".to_string(),
+        AttributeValue::N(expires_at.and_utc().timestamp().to_string()),
+    );
+    dynamodb_values.insert(
+        "timestamp".to_string(),
+        AttributeValue::N(Utc::now().timestamp().to_string()),
+    );
+
+    dynamodb_values
+}
+
+/// Key of the DynamoDB routing item of `host`.
+pub fn routing_key(host: &str) -> HashMap<String, AttributeValue> {
+    let mut dynamodb_values: HashMap<String, AttributeValue> = HashMap::new();
+    dynamodb_values.insert("host".to_string(), AttributeValue::S(host.to_string()));
+
+    dynamodb_values
+}
+
+/// Publishes the routing item of `host`. The put is conditional, so a host
+/// routed to another site is never taken over.
+pub async fn put_routing(
+    dynamodb_client: &dynamodb::Client,
+    host: &str,
+    deployment: &Deployment,
+) -> Result<(), AppError> {
+    dynamodb_client
+        .claim_item(routing_item(host, deployment), "host", "siteId")
+        .await
+        .map_err(|e| match e {
+            AppError::Conflict(_) => {
+                AppError::Conflict(format!("{} is already routed to another site", host))
+            }
+            e => e,
+        })
+}
+
+/// Removes the routing item of `host`, unless another site claimed it since.
+pub async fn release_routing(
+    dynamodb_client: &dynamodb::Client,
+    host: &str,
+    site_id: &str,
+) -> Result<(), AppError> {
+    dynamodb_client
+        .release_item(routing_key(host), "siteId", site_id)
+        .await
+}
+
+/// Points every host of a site to `deployment`. If one of them fails, the
+/// hosts already published are pointed back to `previous_deployment`, or
+/// removed when the site had no deployment yet.
+pub async fn publish_routing(
+    dynamodb_client: &dynamodb::Client,
+    hosts: &[String],
+    deployment: &Deployment,
+    previous_deployment: Option<&Deployment>,
+) -> Result<(), AppError> {
+    for (published_count, host) in hosts.iter().enumerate() {
+        let Err(e) = put_routing(dynamodb_client, host, deployment).await else {
+            continue;
+        };
+
+        for published_host in &hosts[..published_count] {
+            let restored = match previous_deployment {
+                Some(previous_deployment) => {
+                    put_routing(dynamodb_client, published_host, previous_deployment).await
+                }
+                None => {
+                    dynamodb_client
+                        .delete_item(routing_key(published_host))
+                        .await
+                }
+            };
+            if let Err(restore_error) = restored {
+                println!(
+                    "Error restoring the routing of {}: {}",
+                    published_host, restore_error
+                );
+            }
+        }
+
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Index used when a deployment doesn't name one.
+pub const DEFAULT_INDEX_FILE: &str = "index.html";
+
+/// Picks the root index of a deployment among its files: the requested one,
+/// else the current index of the site if it's still deployed, else `index.html`.
+pub fn resolve_index_file(
+    requested: Option<&str>,
+    current: Option<&str>,
+    file_names: &[&str],
+) -> Result<String, AppError> {
+    if let Some(requested) = requested.filter(|requested| !requested.is_empty()) {
+        if !file_names.contains(&requested) {
+            return Err(AppError::Validation(format!(
+                "Index file {} is not part of the deployment",
+                requested
+            )));
+        }
+        return Ok(requested.to_string());
+    }
+
+    current
+        .into_iter()
+        .chain(std::iter::once(DEFAULT_INDEX_FILE))
+        .find(|candidate| file_names.contains(candidate))
+        .map(str::to_string)
+        .ok_or_else(|| {
+            AppError::Validation(format!(
+                "No index file found. Upload an {} or set index_file",
+                DEFAULT_INDEX_FILE
+            ))
+        })
+}
+
+/// Page served for unknown paths when a deployment doesn't name one.
+pub const DEFAULT_NOT_FOUND_FILE: &str = "404.html";
+
+/// Picks the page served with a 404 for unknown paths: the requested one,
+/// else the current one of the site if it's still deployed, else `404.html`
+/// if there is one. SPA deployments serve their index instead.
+pub fn resolve_not_found_file(
+    requested: Option<&str>,
+    current: Option<&str>,
+    spa: bool,
+    file_names: &[&str],
+) -> Result<Option<String>, AppError> {
+    if let Some(requested) = requested.filter(|requested| !requested.is_empty()) {
+        if spa {
+            return Err(AppError::Validation(
+                "SPA sites serve their index file for unknown paths, not_found_file can't be set"
+                    .to_string(),
+            ));
+        }
+        if !file_names.contains(&requested) {
+            return Err(AppError::Validation(format!(
+                "Not found file {} is not part of the deployment",
+                requested
+            )));
+        }
+        return Ok(Some(requested.to_string()));
+    }
+
+    if spa {
+        return Ok(None);
+    }
+
+    Ok(current
+        .into_iter()
+        .chain(std::iter::once(DEFAULT_NOT_FOUND_FILE))
+        .find(|candidate| file_names.contains(candidate))
+        .map(str::to_string))
+}
+
+/// How the pages of a deployment are served.
+pub struct PageSettings {
+    pub index_file: String,
+    /// Unknown paths serve the index file with a 200
+    pub spa: bool,
+    /// Served with a 404 for unknown paths of non-SPA deployments
+    pub not_found_file: Option<String>,
+}
+
+/// Directory a file is the index of: the root for the index file of the
+/// deployment, and their own directory for files with the same name in
+/// subdirectories (e.g. `docs/index.html` serves `docs/`).
+fn index_of(file_name: &str, index_file: &str) -> Option<String> {
+    if file_name == index_file {
+        return Some(String::new());
+    }
+
+    let index_name = index_file.rsplit('/').next().unwrap_or(index_file);
+    match file_name.rsplit_once('/') {
+        Some((directory, name)) if name == index_name => Some(directory.to_string()),
+        _ => None,
+    }
+}
+
+/// Changes how the pages of a stored deployment are served, the directory
+/// indexes of its files following the new index file.
+pub fn update_pages(
+    conn: &mut SqliteConnection,
+    deployment: &Deployment,
+    pages: &PageSettings,
+) -> QueryResult<Deployment> {
+    use crate::schema::deployments::dsl::{
+        deployments, id as deployments_id, index_file, not_found_file, spa, updated_at,
+    };
+    use crate::schema::files::dsl::{
+        deployment_id, files, id as files_id, index_of as file_index_of, is_index,
+    };
+
+    let deployment_files: Vec<File> = files
+        .filter(deployment_id.eq(deployment.id.clone()))
+        .select(File::as_select())
+        .load(conn)?;
+    for file in deployment_files {
+        let new_index_of = index_of(&file.name, &pages.index_file);
+        let new_is_index = file.name == pages.index_file;
+        if new_index_of != file.index_of || new_is_index != file.is_index {
+            diesel::update(files.filter(files_id.eq(file.id.clone())))
+                .set((file_index_of.eq(new_index_of), is_index.eq(new_is_index)))
+                .execute(conn)?;
+        }
+    }
+
+    diesel::update(deployments.filter(deployments_id.eq(deployment.id.clone())))
+        .set((
+            index_file.eq(Some(pages.index_file.clone())),
+            spa.eq(pages.spa),
+            not_found_file.eq(pages.not_found_file.clone()),
+            updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+
+    deployments
+        .filter(deployments_id.eq(deployment.id.clone()))
+        .select(Deployment::as_select())
+        .first(conn)
+}
+
+/// Stores a new pending deployment of the site along with its uploaded files
+/// and rules. The files must have been uploaded under the deployment path.
+pub fn create_deployment(
+    conn: &mut SqliteConnection,
+    site_id: &str,
+    deployment_id: &str,
+    uploaded_files: &[UploadedFile],
+    pages: &PageSettings,
+    config: &DeploymentConfig,
+) -> QueryResult<Deployment> {
+    use crate::schema::deployments::dsl::deployments;
+    use crate::schema::files::dsl::files;
+    use crate::schema::header_rules::dsl::header_rules;
+    use crate::schema::redirect_rules::dsl::redirect_rules;
+
+    let now = Utc::now().naive_utc();
+    let deployment = Deployment {
+        id: deployment_id.to_string(),
+        site_id: site_id.to_string(),
+        status: DeploymentStatus::Pending.as_str().to_string(),
+        file_count: uploaded_files.len() as i64,
+        total_size: uploaded_files.iter().map(|file| file.size).sum(),
+        index_file: Some(pages.index_file.clone()),
+        spa: pages.spa,
+        not_found_file: pages.not_found_file.clone(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    let deployment_path = deployment.path();
+    let new_files: Vec<File> = uploaded_files
+        .iter()
+        .map(|file| {
+            let now = Utc::now().naive_utc();
+            let file_name = file.filename.clone();
+            let file_path = format!("{}{}", deployment_path, file_name);
+            let file_mime_type = file.content_type.clone();
+            File {
+                id: ulid::Ulid::new().to_string(),
+                site_id: site_id.to_string(),
+                name: file_name.clone(),
+                path: file_path,
+                mime_type: file_mime_type,
+                size: file.size,
+                is_index: file_name == pages.index_file,
+                deployment_id: Some(deployment.id.clone()),
+                hash: Some(file.hash.clone()),
+                index_of: index_of(&file_name, &pages.index_file),
+                brotli_size: file.brotli_size,
+                gzip_size: file.gzip_size,
+                cache_control: cache_control_for(
+                    &config.cache_control,
+                    &file_name,
+                    &file.content_type,
+                ),
+                created_at: now,
+                updated_at: now,
+            }
+        })
+        .collect();
+
+    let new_redirect_rules: Vec<RedirectRule> = config
+        .redirects
+        .iter()
+        .enumerate()
+        .map(|(position, rule)| RedirectRule {
+            id: ulid::Ulid::new().to_string(),
+            site_id: site_id.to_string(),
+            deployment_id: deployment.id.clone(),
+            position: position as i32,
+            from_path: rule.from.clone(),
+            to_path: rule.to.clone(),
+            status: rule.status as i32,
+            force: rule.force,
+            created_at: now,
+            updated_at: now,
+        })
+        .collect();
+
+    let new_header_rules: Vec<HeaderRule> = config
+        .headers
+        .iter()
+        .enumerate()
+        .map(|(position, rule)| HeaderRule {
+            id: ulid::Ulid::new().to_string(),
+            site_id: site_id.to_string(),
+            deployment_id: deployment.id.clone(),
+            position: position as i32,
+            path: rule.path.clone(),
+            name: rule.name.clone(),
+            value: rule.value.clone(),
+            created_at: now,
+            updated_at: now,
+        })
+        .collect();
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::insert_into(deployments)
+            .values(&deployment)
+            .execute(conn)?;
+        diesel::insert_into(files)
+            .values(&new_files)
+            .execute(conn)?;
+        diesel::insert_into(redirect_rules)
+            .values(&new_redirect_rules)
+            .execute(conn)?;
+        diesel::insert_into(header_rules)
+            .values(&new_header_rules)
+            .execute(conn)?;
+        Ok(())
+    })?;
+
+    Ok(deployment)
+}
+
+/// Makes `deployment_id` the active deployment of the site, every other
+/// deployment of the site becomes inactive. The site index and fallback
+/// pages follow the ones of the deployment.
+pub fn activate_deployment(
+    conn: &mut SqliteConnection,
+    site_id_to_update: &str,
+    deployment_id: &str,
+) -> QueryResult<()> {
+    use crate::schema::deployments::dsl::{
+        deployments, id, site_id, status, updated_at as deployment_updated_at,
+    };
+    use crate::schema::sites::dsl::{
+        active_deployment_id, id as sites_id, index_file as site_index_file,
+        not_found_file as site_not_found_file, sites, spa as site_spa,
+        updated_at as site_updated_at,
+    };
+
+    conn.transaction(|conn| {
+        let now = Utc::now().naive_utc();
+
+        diesel::update(
+            deployments
+                .filter(site_id.eq(site_id_to_update))
+                .filter(status.eq(DeploymentStatus::Active.as_str())),
+        )
+        .set((
+            status.eq(DeploymentStatus::Inactive.as_str()),
+            deployment_updated_at.eq(now),
+        ))
+        .execute(conn)?;
+
+        diesel::update(deployments.filter(id.eq(deployment_id)))
+            .set((
+                status.eq(DeploymentStatus::Active.as_str()),
+                deployment_updated_at.eq(now),
+            ))
+            .execute(conn)?;
+
+        let activated: Deployment = deployments
+            .filter(id.eq(deployment_id))
+            .select(Deployment::as_select())
+            .first(conn)?;
+
+        diesel::update(sites.filter(sites_id.eq(site_id_to_update)))
+            .set((
+                active_deployment_id.eq(deployment_id),
+                site_spa.eq(activated.spa),
+                site_not_found_file.eq(activated.not_found_file),
+                site_updated_at.eq(now),
+            ))
+            .execute(conn)?;
+
+        // Deployments made before indexes were recorded keep the site index
+        if let Some(activated_index_file) = activated.index_file {
+            diesel::update(sites.filter(sites_id.eq(site_id_to_update)))
+                .set(site_index_file.eq(activated_index_file))
+                .execute(conn)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Files of the active deployment of the site, keyed by name.
+fn active_files(conn: &mut SqliteConnection, site: &Site) -> QueryResult<HashMap<String, File>> {
+    use crate::schema::files::dsl::{deployment_id, files, site_id};
+
+    let active_files: Vec<File> = files
+        .filter(site_id.eq(site.id.clone()))
+        .filter(deployment_id.is(site.active_deployment_id.clone()))
+        .select(File::as_select())
+        .load::<File>(conn)?;
+
+    Ok(active_files
+        .into_iter()
+        .map(|file| (file.name.clone(), file))
+        .collect())
+}
+
+fn is_unchanged(file: &File, entry: &ManifestEntry) -> bool {
+    file.hash.as_deref() == Some(entry.hash.as_str()) && file.size == entry.size
+}
+
+/// Resolves the manifest entries which weren't uploaded to the unchanged files
+/// of the active deployment, so they can be reused by the new deployment.
+pub fn files_to_reuse(
+    conn: &mut SqliteConnection,
+    site: &Site,
+    manifest: &Manifest,
+    uploaded_names: &[String],
+) -> Result<Vec<File>, AppError> {
+    let mut active_files = active_files(conn, site)?;
+
+    let mut reused_files = Vec::new();
+    let mut missing_paths = Vec::new();
+    for entry in &manifest.files {
+        if uploaded_names.contains(&entry.path) {
+            continue;
+        }
+
+        match active_files.remove(&entry.path) {
+            Some(file) if is_unchanged(&file, entry) => reused_files.push(file),
+            _ => missing_paths.push(entry.path.clone()),
+        }
+    }
+
+    if !missing_paths.is_empty() {
+        return Err(AppError::Validation(format!(
+            "Files are neither uploaded nor unchanged: {}",
+            missing_paths.join(", ")
+        )));
+    }
+
+    Ok(reused_files)
+}
+
+/// Undoes the database changes of a deployment whose publication failed:
+/// the previous deployment becomes active again and the new one is removed,
+/// along with the site itself when it was created by this deployment.
+fn revert_deployment(
+    conn: &mut SqliteConnection,
+    site: &Site,
+    is_new_site: bool,
+    deployment_id: &str,
+) -> QueryResult<()> {
+    use crate::schema::deployments::dsl::{deployments, id as deployments_id};
+    use crate::schema::files::dsl::{deployment_id as file_deployment_id, files};
+    use crate::schema::header_rules::dsl::{deployment_id as header_deployment_id, header_rules};
+    use crate::schema::redirect_rules::dsl::{deployment_id as rule_deployment_id, redirect_rules};
+    use crate::schema::sites::dsl::{active_deployment_id, id as sites_id, sites};
+
+    conn.transaction(|conn| {
+        match &site.active_deployment_id {
+            Some(previous_deployment_id) => {
+                activate_deployment(conn, &site.id, previous_deployment_id)?;
+            }
+            None => {
+                diesel::update(sites.filter(sites_id.eq(site.id.clone())))
+                    .set(active_deployment_id.eq(None::<String>))
+                    .execute(conn)?;
+            }
+        }
+
+        diesel::delete(files.filter(file_deployment_id.eq(deployment_id))).execute(conn)?;
+        diesel::delete(redirect_rules.filter(rule_deployment_id.eq(deployment_id)))
+            .execute(conn)?;
+        diesel::delete(header_rules.filter(header_deployment_id.eq(deployment_id)))
+            .execute(conn)?;
+        diesel::delete(deployments.filter(deployments_id.eq(deployment_id))).execute(conn)?;
+
+        if is_new_site {
+            diesel::delete(sites.filter(sites_id.eq(site.id.clone()))).execute(conn)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Removes files uploaded for a deployment that couldn't be published.
+async fn discard_files(storage_client: &dyn Storage, keys: Vec<String>) {
+    if let Err(e) = storage_client.delete_files(keys).await {
+        println!("Error discarding uploaded files: {}", e);
+    }
+}
+
+/// Rules of a deployment, rules of previous deployments are kept
+/// so they apply again after a rollback.
+pub fn config_of(
+    conn: &mut SqliteConnection,
+    deployment_id: &str,
+) -> QueryResult<DeploymentConfig> {
+    use crate::schema::header_rules::dsl::{
+        deployment_id as header_deployment_id, header_rules, position as header_position,
+    };
+    use crate::schema::redirect_rules::dsl::{
+        deployment_id as rule_deployment_id, position as rule_position, redirect_rules,
+    };
+
+    let redirect_rules_list: Vec<RedirectRule> = redirect_rules
+        .filter(rule_deployment_id.eq(deployment_id))
+        .order(rule_position.asc())
+        .select(RedirectRule::as_select())
+        .load(conn)?;
+    let header_rules_list: Vec<HeaderRule> = header_rules
+        .filter(header_deployment_id.eq(deployment_id))
+        .order(header_position.asc())
+        .select(HeaderRule::as_select())
+        .load(conn)?;
+
+    Ok(DeploymentConfig {
+        redirects: redirect_rules_list
+            .iter()
+            .map(redirects::Rule::from)
+            .collect(),
+        headers: header_rules_list.iter().map(headers::Rule::from).collect(),
+        // Resolved into the cache policy of each file when deploying
+        cache_control: Vec::new(),
+    })
+}
+
+/// Removes the rules of deployments from the edge.
+pub async fn unpublish_config(
+    kvs_client: &cloudfront_key_value::Client,
+    deployment_configs: Vec<(String, DeploymentConfig)>,
+) -> Result<(), AppError> {
+    let keys: Vec<String> = deployment_configs
+        .iter()
+        .flat_map(|(deployment_id, config)| config.edge_entries(deployment_id))
+        .map(|(key, _)| key)
+        .collect();
+
+    if !kvs_client.is_configured() || keys.is_empty() {
+        return Ok(());
+    }
+
+    kvs_client.update_values(Vec::new(), keys).await
+}
+
+/// Headers stored with a file: its content type and cache policy, overridden
+/// by the header rules matching its path or the directory it is the index of.
+fn object_headers(
+    config: &DeploymentConfig,
+    file_name: &str,
+    content_type: &str,
+    index_file: &str,
+) -> ObjectHeaders {
+    let mut paths = vec![format!("/{}", file_name)];
+    match index_of(file_name, index_file).as_deref() {
+        Some("") => paths.push("/".to_string()),
+        Some(directory) => paths.push(format!("/{}/", directory)),
+        None => {}
+    }
+
+    let mut object_headers = ObjectHeaders {
+        content_type: content_type.to_string(),
+        cache_control: cache_control_for(&config.cache_control, file_name, content_type),
+        content_encoding: None,
+    };
+    for (name, value) in paths
+        .iter()
+        .flat_map(|path| headers::headers_for(&config.headers, path))
+    {
+        if name.eq_ignore_ascii_case("content-type") {
+            object_headers.content_type = value;
+        } else if name.eq_ignore_ascii_case("cache-control") {
+            object_headers.cache_control = Some(value);
+        }
+    }
+
+    object_headers
+}
+
+/// Content of a new deployment.
+pub struct DeploymentUpload {
+    pub files: Vec<TempFile>,
+    /// Unchanged files of the active deployment which are copied
+    /// instead of uploaded again
+    pub reused_files: Vec<File>,
+    pub pages: PageSettings,
+    pub config: DeploymentConfig,
+}
+
+/// Uploads, stores and publishes a new deployment of `site`.
+///
+/// Each step is compensated if a later one fails, so a failed deployment
+/// leaves neither orphan objects nor rows behind. When `is_new_site` is set,
+/// the site row is only inserted together with its first deployment so a
+/// failed upload never claims the host. Rules are published to the edge
+/// before the routing item, so they apply as soon as the site does.
+pub async fn publish_deployment(
+    conn: &mut SqliteConnection,
+    storage_client: &dyn Storage,
+    dynamodb_client: &dynamodb::Client,
+    kvs_client: &cloudfront_key_value::Client,
+    site: &Site,
+    is_new_site: bool,
+    upload: DeploymentUpload,
+) -> Result<Deployment, AppError> {
+    use crate::schema::deployments::dsl::{deployments, id as deployments_id};
+    use crate::schema::sites::dsl::sites;
+
+    let DeploymentUpload {
+        files: uploading_files,
+        reused_files,
+        pages,
+        config,
+    } = upload;
+
+    let previous_deployment: Option<Deployment> = match &site.active_deployment_id {
+        Some(active_deployment_id) => deployments
+            .filter(deployments_id.eq(active_deployment_id))
+            .select(Deployment::as_select())
+            .first(conn)
+            .optional()?,
+        None => None,
+    };
+
+    // The deployment path isn't served until the routing item points to it,
+    // so it is used as the staging area of the upload
+    let deployment_id = ulid::Ulid::new().to_string();
+    let deployment_path = Deployment::path_for(&site.id, &deployment_id);
+    let mut copied_keys: Vec<(String, String)> = Vec::new();
+    for file in &reused_files {
+        let key = format!("{}{}", deployment_path, file.name);
+        for encoding in file.encodings() {
+            copied_keys.push((encoding.variant_key(&file.path), encoding.variant_key(&key)));
+        }
+        copied_keys.push((file.path.clone(), key));
+    }
+
+    // Variants are only uploaded for compressible files, the missing
+    // ones are ignored when the staged files are discarded
+    let mut staged_keys: Vec<String> = copied_keys.iter().map(|(_, to)| to.clone()).collect();
+    for file in &uploading_files {
+        let (Some(file_name), Some(content_type)) = (&file.file_name, &file.content_type) else {
+            continue;
+        };
+        let key = format!("{}{}", deployment_path, file_name);
+        if is_compressible(content_type.as_ref(), file.size as u64) {
+            staged_keys.extend(Encoding::ALL.map(|encoding| encoding.variant_key(&key)));
+        }
+        staged_keys.push(key);
+    }
+
+    // Every copy gets its headers again, the rules may have changed
+    // since the file was uploaded
+    let mut staged_headers: HashMap<String, ObjectHeaders> = uploading_files
+        .iter()
+        .filter_map(|file| Some((file.file_name.as_ref()?, file.content_type.as_ref()?)))
+        .map(|(file_name, content_type)| (file_name.as_str(), content_type.to_string()))
+        .chain(
+            reused_files
+                .iter()
+                .map(|file| (file.name.as_str(), file.mime_type.clone())),
+        )
+        .map(|(file_name, content_type)| {
+            (
+                format!("{}{}", deployment_path, file_name),
+                object_headers(&config, file_name, &content_type, &pages.index_file),
+            )
+        })
+        .collect();
+    for file in &reused_files {
+        let key = format!("{}{}", deployment_path, file.name);
+        let Some(headers) = staged_headers.get(&key).cloned() else {
+            continue;
+        };
+        for encoding in file.encodings() {
+            staged_headers.insert(
+                encoding.variant_key(&key),
+                ObjectHeaders {
+                    content_encoding: Some(encoding.as_str().to_string()),
+                    ..headers.clone()
+                },
+            );
+        }
+    }
+
+    let staged = async {
+        let mut uploaded_files = storage_client
+            .upload_files(uploading_files, &deployment_path, &staged_headers)
+            .await?;
+
+        // Unchanged files are copied from the previous deployment
+        // instead of being uploaded again
+        storage_client
+            .copy_files(copied_keys, &staged_headers)
+            .await?;
+        uploaded_files.extend(reused_files.into_iter().map(|file| {
+            let key = format!("{}{}", deployment_path, file.name);
+            let mut uploaded_file = UploadedFile::new(
+                file.name,
+                file.mime_type,
+                file.size,
+                file.hash.unwrap_or_default(),
+                &key,
+                storage_client.url(&key),
+            );
+            uploaded_file.brotli_size = file.brotli_size;
+            uploaded_file.gzip_size = file.gzip_size;
+            uploaded_file
+        }));
+
+        Ok::<_, AppError>(uploaded_files)
+    };
+
+    let uploaded_files = match staged.await {
+        Ok(uploaded_files) => uploaded_files,
+        Err(e) => {
+            discard_files(storage_client, staged_keys).await;
+            return Err(e);
+        }
+    };
+
+    let saved = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        if is_new_site {
+            diesel::insert_into(sites).values(site).execute(conn)?;
+        }
+
+        let deployment = create_deployment(
+            conn,
+            &site.id,
+            &deployment_id,
+            &uploaded_files,
+            &pages,
+            &config,
+        )?;
+        activate_deployment(conn, &site.id, &deployment.id)?;
+
+        Ok(deployment)
+    });
+
+    let deployment = match saved {
+        Ok(deployment) => deployment,
+        Err(e) => {
+            discard_files(storage_client, staged_keys).await;
+            return Err(e.into());
+        }
+    };
+
+    let published = async {
+        let hosts = site_hosts(conn, site)?;
+
+        if kvs_client.is_configured() && !config.is_empty() {
+            kvs_client
+                .update_values(config.edge_entries(&deployment.id), Vec::new())
+                .await?;
+        }
+
+        let routed = publish_routing(
+            dynamodb_client,
+            &hosts,
+            &deployment,
+            previous_deployment.as_ref(),
+        )
+        .await;
+        if routed.is_err() {
+            let deployment_configs = vec![(deployment.id.clone(), config)];
+            if let Err(e) = unpublish_config(kvs_client, deployment_configs).await {
+                println!("Error removing the rules of {}: {}", deployment.id, e);
+            }
+        }
+        routed
+    }
+    .await;
+
+    if let Err(e) = published {
+        if let Err(revert_error) = revert_deployment(conn, site, is_new_site, &deployment.id) {
+            println!(
+                "Error reverting deployment {}: {}",
+                deployment.id, revert_error
+            );
+        }
+        discard_files(storage_client, staged_keys).await;
+        return Err(e);
+    }
+
+    Ok(deployment)
+}
+
+pub async fn list_deployments(
+    path_data: web::Path<String>,
+    pool: web::Data<DbPool>,
+    caller: web::ReqData<Caller>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::deployments::dsl::{created_at, deployments, site_id as deployment_site_id};
+
+    let site_id = path_data.into_inner();
+    let mut conn = pool.get()?;
+
+    let site = find_site(&mut conn, &site_id, &caller)?;
+
+    let deployments_list: Vec<Deployment> = deployments
+        .filter(deployment_site_id.eq(site.id.clone()))
+        .order(created_at.desc())
+        .select(Deployment::as_select())
+        .load::<Deployment>(&mut conn)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "deployments": deployments_list,
+        "active_deployment_id": site.active_deployment_id,
+        "total": deployments_list.len(),
+    })))
+}
+
+/// Compares a manifest with the active deployment and returns the paths
+/// the client has to upload, every other file can be reused.
+pub async fn diff_deployment(
+    path_data: web::Path<String>,
+    pool: web::Data<DbPool>,
+    caller: web::ReqData<Caller>,
+    manifest: web::Json<Manifest>,
+) -> Result<HttpResponse, AppError> {
+    let site_id = path_data.into_inner();
+    let mut conn = pool.get()?;
+
+    let site = find_site(&mut conn, &site_id, &caller)?;
+    let active_files = active_files(&mut conn, &site)?;
+
+    let missing_paths: Vec<&str> = manifest
+        .files
+        .iter()
+        .filter(|entry| {
+            !active_files
+                .get(&entry.path)
+                .is_some_and(|file| is_unchanged(file, entry))
+        })
+        .map(|entry| entry.path.as_str())
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "missing": missing_paths,
+        "reused": manifest.files.len() - missing_paths.len(),
+        "total": manifest.files.len(),
+    })))
+}
+
+pub async fn activate(
+    path_data: web::Path<(String, String)>,
+    pool: web::Data<DbPool>,
+    caller: web::ReqData<Caller>,
+    dynamodb_client: web::Data<dynamodb::Client>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::deployments::dsl::{deployments, id, site_id};
+
+    let (site_id_to_update, deployment_id) = path_data.into_inner();
+    let mut conn = pool.get()?;
+
+    let site = find_site(&mut conn, &site_id_to_update, &caller)?;
+
+    let deployment: Deployment = deployments
+        .filter(id.eq(deployment_id.clone()))
+        .filter(site_id.eq(site.id.clone()))
+        .select(Deployment::as_select())
+        .first(&mut conn)
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("Deployment not found".to_string()))?;
+
+    if deployment.status == DeploymentStatus::Pending.as_str() {
+        return Err(AppError::Validation(
+            "Deployment is still pending and can't be activated".to_string(),
+        ));
+    }
+
+    let previous_deployment: Option<Deployment> = match &site.active_deployment_id {
+        Some(active_deployment_id) => deployments
+            .filter(id.eq(active_deployment_id))
+            .select(Deployment::as_select())
+            .first(&mut conn)
+            .optional()?,
+        None => None,
+    };
+
+    // Routed first, so the site never points to a deployment its hosts don't serve
+    let hosts = site_hosts(&mut conn, &site)?;
+    publish_routing(
+        &dynamodb_client,
+        &hosts,
+        &deployment,
+        previous_deployment.as_ref(),
+    )
+    .await?;
+
+    if let Err(e) = activate_deployment(&mut conn, &site.id, &deployment.id) {
+        for host in &hosts {
+            let restored = match &previous_deployment {
+                Some(previous_deployment) => {
+                    put_routing(&dynamodb_client, host, previous_deployment).await
+                }
+                None => dynamodb_client.delete_item(routing_key(host)).await,
+            };
+            if let Err(restore_error) = restored {
+                println!("Error restoring the routing of {}: {}", host, restore_error);
+            }
+        }
+        return Err(e.into());
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": format!("Deployment {} is now active", deployment.id)
+    })))
+}
@@ -0,0 +1,280 @@
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::handlers::deployments::{put_routing, routing_key};
+use crate::handlers::sites::find_site;
+use crate::middleware::auth::Caller;
+use crate::models::{Deployment, Domain, DomainStatus, Site};
+use crate::services::{dns::TxtResolver, dynamodb};
+use crate::utils::hostname::HostPolicy;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use diesel::prelude::*;
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize)]
+pub struct CreateDomainBody {
+    host: String,
+}
+
+fn generate_verification_token() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("nanohost-verification={}", token)
+}
+
+/// Hosts the site is served on: its primary host and every verified domain.
+pub fn site_hosts(conn: &mut SqliteConnection, site: &Site) -> QueryResult<Vec<String>> {
+    use crate::schema::domains::dsl::{domains, host, site_id, status};
+
+    let verified_hosts: Vec<String> = domains
+        .filter(site_id.eq(site.id.clone()))
+        .filter(status.eq(DomainStatus::Verified.as_str()))
+        .select(host)
+        .load::<String>(conn)?;
+
+    Ok(std::iter::once(site.host.clone())
+        .chain(verified_hosts)
+        .collect())
+}
+
+/// Whether a host is used by a site, a verified custom domain, or the redirect
+/// of a renamed site other than `owner_site_id`. Pending domains don't count,
+/// so a domain nobody verifies can't hold its host.
+pub fn is_host_taken(
+    conn: &mut SqliteConnection,
+    host_to_check: &str,
+    owner_site_id: Option<&str>,
+) -> QueryResult<bool> {
+    use crate::schema::domains::dsl::{domains, host as domain_host, status};
+    use crate::schema::host_redirects::dsl::{
+        host as redirect_host, host_redirects, site_id as redirect_site_id,
+    };
+    use crate::schema::sites::dsl::{host as site_host, sites};
+
+    let site_count: i64 = sites
+        .filter(site_host.eq(host_to_check))
+        .count()
+        .get_result(conn)?;
+    let domain_count: i64 = domains
+        .filter(domain_host.eq(host_to_check))
+        .filter(status.eq(DomainStatus::Verified.as_str()))
+        .count()
+        .get_result(conn)?;
+    let redirect_count: i64 = host_redirects
+        .filter(redirect_host.eq(host_to_check))
+        .filter(redirect_site_id.nullable().ne(owner_site_id))
+        .count()
+        .get_result(conn)?;
+
+    Ok(site_count + domain_count + redirect_count > 0)
+}
+
+/// Looks for the verification token in the TXT record of a pending domain
+/// and, once found, publishes the domain to the routing table.
+/// Returns whether the domain is verified. Several sites can wait for the
+/// same host, it goes to the first one verified.
+pub async fn verify_domain(
+    conn: &mut SqliteConnection,
+    resolver: &dyn TxtResolver,
+    dynamodb_client: &dynamodb::Client,
+    domain: &Domain,
+) -> Result<bool, AppError> {
+    use crate::schema::deployments::dsl::{deployments, id as deployments_id};
+    use crate::schema::domains::dsl::{domains, id, status, updated_at, verified_at};
+    use crate::schema::sites::dsl::{id as sites_id, sites};
+
+    if domain.status == DomainStatus::Verified.as_str() {
+        return Ok(true);
+    }
+
+    let records = resolver.txt_records(&domain.verification_record()).await?;
+    if !records
+        .iter()
+        .any(|record| record.trim() == domain.verification_token)
+    {
+        return Ok(false);
+    }
+
+    if is_host_taken(conn, &domain.host, Some(&domain.site_id))? {
+        return Err(AppError::Conflict("Domain is already taken".to_string()));
+    }
+
+    // The domain is published before being marked as verified,
+    // so a failed put is retried by the next verification
+    let site: Site = sites
+        .filter(sites_id.eq(domain.site_id.clone()))
+        .select(Site::as_select())
+        .first(conn)?;
+    if let Some(active_deployment_id) = &site.active_deployment_id {
+        let deployment: Deployment = deployments
+            .filter(deployments_id.eq(active_deployment_id))
+            .select(Deployment::as_select())
+            .first(conn)?;
+        put_routing(dynamodb_client, &domain.host, &deployment).await?;
+    }
+
+    let now = Utc::now().naive_utc();
+    diesel::update(domains.filter(id.eq(domain.id.clone())))
+        .set((
+            status.eq(DomainStatus::Verified.as_str()),
+            verified_at.eq(Some(now)),
+            updated_at.eq(now),
+        ))
+        .execute(conn)?;
+
+    Ok(true)
+}
+
+fn find_domain(
+    conn: &mut SqliteConnection,
+    site: &Site,
+    domain_id: &str,
+) -> Result<Domain, AppError> {
+    use crate::schema::domains::dsl::{domains, id, site_id};
+
+    domains
+        .filter(id.eq(domain_id))
+        .filter(site_id.eq(site.id.clone()))
+        .select(Domain::as_select())
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("Domain not found".to_string()))
+}
+
+/// Attaches a custom domain to the site. The domain is only served once the
+/// verification token is found in its `_nanohost` TXT record.
+pub async fn create_domain(
+    path_data: web::Path<String>,
+    pool: web::Data<DbPool>,
+    caller: web::ReqData<Caller>,
+    host_policy: web::Data<HostPolicy>,
+    body: web::Json<CreateDomainBody>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::domains::dsl::{domains, host, site_id as domain_site_id};
+
+    let site_id = path_data.into_inner();
+    let mut conn = pool.get()?;
+
+    let site = find_site(&mut conn, &site_id, &caller)?;
+
+    let new_host = host_policy.custom_host(&body.host)?;
+    // Pending domains of other sites don't block the host, but the site
+    // can only wait for it once
+    let site_domain_count: i64 = domains
+        .filter(domain_site_id.eq(site.id.clone()))
+        .filter(host.eq(&new_host))
+        .count()
+        .get_result(&mut conn)?;
+    if site_domain_count > 0 || is_host_taken(&mut conn, &new_host, None)? {
+        return Err(AppError::Conflict("Domain is already taken".to_string()));
+    }
+
+    let now = Utc::now().naive_utc();
+    let new_domain = Domain {
+        id: ulid::Ulid::new().to_string(),
+        site_id: site.id.clone(),
+        host: new_host,
+        verification_token: generate_verification_token(),
+        status: DomainStatus::Pending.as_str().to_string(),
+        verified_at: None,
+        created_at: now,
+        updated_at: now,
+    };
+
+    diesel::insert_into(domains)
+        .values(&new_domain)
+        .execute(&mut conn)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "domain": new_domain,
+        "verification": {
+            "type": "TXT",
+            "name": new_domain.verification_record(),
+            "value": new_domain.verification_token,
+        },
+    })))
+}
+
+pub async fn list_domains(
+    path_data: web::Path<String>,
+    pool: web::Data<DbPool>,
+    caller: web::ReqData<Caller>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::domains::dsl::{created_at, domains, site_id as domain_site_id};
+
+    let site_id = path_data.into_inner();
+    let mut conn = pool.get()?;
+
+    let site = find_site(&mut conn, &site_id, &caller)?;
+
+    let domains_list: Vec<Domain> = domains
+        .filter(domain_site_id.eq(site.id.clone()))
+        .order(created_at.asc())
+        .select(Domain::as_select())
+        .load::<Domain>(&mut conn)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "domains": domains_list,
+        "total": domains_list.len(),
+    })))
+}
+
+/// Verifies a domain right away instead of waiting for the verification worker.
+pub async fn verify(
+    path_data: web::Path<(String, String)>,
+    pool: web::Data<DbPool>,
+    caller: web::ReqData<Caller>,
+    resolver: web::Data<dyn TxtResolver>,
+    dynamodb_client: web::Data<dynamodb::Client>,
+) -> Result<HttpResponse, AppError> {
+    let (site_id, domain_id) = path_data.into_inner();
+    let mut conn = pool.get()?;
+
+    let site = find_site(&mut conn, &site_id, &caller)?;
+    let domain = find_domain(&mut conn, &site, &domain_id)?;
+
+    let verified = verify_domain(&mut conn, resolver.get_ref(), &dynamodb_client, &domain).await?;
+
+    let message = match verified {
+        true => format!("Domain {} is verified", domain.host),
+        false => format!(
+            "Verification token not found in the TXT record {}",
+            domain.verification_record()
+        ),
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": message,
+        "verified": verified,
+    })))
+}
+
+pub async fn delete_domain(
+    path_data: web::Path<(String, String)>,
+    pool: web::Data<DbPool>,
+    caller: web::ReqData<Caller>,
+    dynamodb_client: web::Data<dynamodb::Client>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::domains::dsl::{domains, id};
+
+    let (site_id, domain_id) = path_data.into_inner();
+    let mut conn = pool.get()?;
+
+    let site = find_site(&mut conn, &site_id, &caller)?;
+    let domain = find_domain(&mut conn, &site, &domain_id)?;
+
+    if domain.status == DomainStatus::Verified.as_str() {
+        dynamodb_client
+            .delete_item(routing_key(&domain.host))
+            .await?;
+    }
+
+    diesel::delete(domains.filter(id.eq(domain.id.clone()))).execute(&mut conn)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": format!("Domain {} removed", domain.host)
+    })))
+}
@@ -1 +1,5 @@
+pub mod deployments;
+pub mod domains;
+pub mod serve;
 pub mod sites;
+pub mod users;
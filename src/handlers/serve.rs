@@ -0,0 +1,306 @@
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::models::{DomainStatus, File, HeaderRule, HostRedirect, RedirectRule, Site};
+use crate::services::storage::Storage;
+use crate::utils::compression::Encoding;
+use crate::utils::headers::{self, headers_for};
+use crate::utils::redirects::rewrite;
+use actix_web::{
+    guard::GuardContext,
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use percent_encoding::percent_decode_str;
+use tokio_util::io::ReaderStream;
+
+/// Strips the port (if any) from a `Host` header value.
+fn host_without_port(value: &str) -> &str {
+    value.split(':').next().unwrap_or(value)
+}
+
+/// Guard matching every request that isn't addressed to the API host,
+/// so hosted sites can use any path (including `/sites`).
+pub fn is_site_request(ctx: &GuardContext, api_host: &str) -> bool {
+    match ctx.head().headers().get(header::HOST) {
+        Some(value) => value
+            .to_str()
+            .is_ok_and(|value| host_without_port(value) != host_without_port(api_host)),
+        None => false,
+    }
+}
+
+/// Resolves a request path to a file of the active deployment: the file
+/// itself, else the index of the directory (`/docs` and `/docs/` both serve
+/// `docs/index.html`).
+fn find_file(
+    conn: &mut SqliteConnection,
+    site: &Site,
+    request_path: &str,
+) -> QueryResult<Option<File>> {
+    use crate::schema::files::dsl::{deployment_id, files, index_of, name, site_id};
+
+    let active_files = files
+        .filter(site_id.eq(site.id.clone()))
+        .filter(deployment_id.is(site.active_deployment_id.clone()));
+
+    if !request_path.is_empty() && !request_path.ends_with('/') {
+        let file = active_files
+            .clone()
+            .filter(name.eq(request_path))
+            .select(File::as_select())
+            .first(conn)
+            .optional()?;
+        if file.is_some() {
+            return Ok(file);
+        }
+    }
+
+    let file = active_files
+        .clone()
+        .filter(index_of.eq(request_path.trim_end_matches('/')))
+        .select(File::as_select())
+        .first(conn)
+        .optional()?;
+    if file.is_some() || !request_path.is_empty() {
+        return Ok(file);
+    }
+
+    // Deployments made before directory indexes were recorded
+    // only know the index file of the site
+    match &site.index_file {
+        Some(site_index_file) => active_files
+            .filter(name.eq(site_index_file))
+            .select(File::as_select())
+            .first(conn)
+            .optional(),
+        None => Ok(None),
+    }
+}
+
+/// First redirect rule of the active deployment matching the request path,
+/// along with its target. Unless forced, rules don't apply to existing files.
+fn find_redirect(
+    conn: &mut SqliteConnection,
+    site: &Site,
+    request_path: &str,
+    file_exists: bool,
+) -> QueryResult<Option<(RedirectRule, String)>> {
+    use crate::schema::redirect_rules::dsl::{deployment_id, force, position, redirect_rules};
+
+    let Some(active_deployment_id) = &site.active_deployment_id else {
+        return Ok(None);
+    };
+
+    let mut query = redirect_rules
+        .filter(deployment_id.eq(active_deployment_id))
+        .into_boxed();
+    if file_exists {
+        query = query.filter(force.eq(true));
+    }
+
+    let rules: Vec<RedirectRule> = query
+        .order(position.asc())
+        .select(RedirectRule::as_select())
+        .load(conn)?;
+
+    let request_path = format!("/{}", request_path);
+    Ok(rules.into_iter().find_map(|rule| {
+        rewrite(&rule.from_path, &rule.to_path, &request_path).map(|target| (rule, target))
+    }))
+}
+
+/// Page served when no file matches the request: the index of SPA sites
+/// with a 200, else the not found page of the site with a 404.
+fn find_fallback(
+    conn: &mut SqliteConnection,
+    site: &Site,
+) -> QueryResult<Option<(File, StatusCode)>> {
+    if site.spa {
+        return Ok(find_file(conn, site, "")?.map(|file| (file, StatusCode::OK)));
+    }
+
+    match &site.not_found_file {
+        Some(not_found_file) => {
+            Ok(find_file(conn, site, not_found_file)?.map(|file| (file, StatusCode::NOT_FOUND)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Headers of the header rules of the active deployment matching the request path.
+fn find_headers(
+    conn: &mut SqliteConnection,
+    site: &Site,
+    request_path: &str,
+) -> QueryResult<Vec<(String, String)>> {
+    use crate::schema::header_rules::dsl::{deployment_id, header_rules, position};
+
+    let Some(active_deployment_id) = &site.active_deployment_id else {
+        return Ok(Vec::new());
+    };
+
+    let rules: Vec<HeaderRule> = header_rules
+        .filter(deployment_id.eq(active_deployment_id))
+        .order(position.asc())
+        .select(HeaderRule::as_select())
+        .load(conn)?;
+    let rules: Vec<headers::Rule> = rules.iter().map(headers::Rule::from).collect();
+
+    Ok(headers_for(&rules, &format!("/{}", request_path)))
+}
+
+/// Whether an `Accept-Encoding` header accepts an encoding, ignoring the
+/// preferences of the client beyond `q=0`.
+fn accepts_encoding(accept_encoding: &str, encoding: Encoding) -> bool {
+    accept_encoding.split(',').any(|value| {
+        let mut params = value.split(';').map(str::trim);
+        let coding = params.next().unwrap_or_default();
+        let is_refused = params.any(|param| {
+            param
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+
+        (coding.eq_ignore_ascii_case(encoding.as_str()) || coding == "*") && !is_refused
+    })
+}
+
+/// Precompressed variant of the file to serve, brotli being preferred.
+fn find_encoding(req: &HttpRequest, file: &File) -> Option<Encoding> {
+    let accept_encoding = req.headers().get(header::ACCEPT_ENCODING)?.to_str().ok()?;
+    file.encodings()
+        .into_iter()
+        .find(|encoding| accepts_encoding(accept_encoding, *encoding))
+}
+
+/// Site a previous host of a renamed site redirects to, until the redirect expires.
+fn find_host_redirect(
+    conn: &mut SqliteConnection,
+    request_host: &str,
+) -> QueryResult<Option<Site>> {
+    use crate::schema::host_redirects::dsl::{expires_at, host, host_redirects};
+    use crate::schema::sites::dsl::sites;
+
+    host_redirects
+        .inner_join(sites)
+        .filter(host.eq(request_host))
+        .filter(expires_at.gt(Utc::now().naive_utc()))
+        .select((HostRedirect::as_select(), Site::as_select()))
+        .first::<(HostRedirect, Site)>(conn)
+        .optional()
+        .map(|redirect| redirect.map(|(_, site)| site))
+}
+
+pub async fn serve_site(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    storage_client: web::Data<dyn Storage>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::domains::dsl::{domains, host as domain_host, site_id, status};
+    use crate::schema::sites::dsl::*;
+
+    let request_host = host_without_port(req.connection_info().host()).to_lowercase();
+    let mut conn = pool.get()?;
+
+    // Custom domains are only served once verified
+    let verified_domain_site_ids = domains
+        .filter(domain_host.eq(request_host.clone()))
+        .filter(status.eq(DomainStatus::Verified.as_str()))
+        .select(site_id);
+
+    let site: Option<Site> = sites
+        .filter(
+            host.eq(request_host.clone())
+                .or(id.eq_any(verified_domain_site_ids)),
+        )
+        .select(Site::as_select())
+        .first(&mut conn)
+        .optional()?;
+
+    let site = match site {
+        Some(site) => site,
+        None => {
+            let moved_site = find_host_redirect(&mut conn, &request_host)?
+                .ok_or_else(|| AppError::NotFound("Site not found".to_string()))?;
+            let location = format!(
+                "{}://{}{}",
+                req.connection_info().scheme(),
+                moved_site.host,
+                req.uri()
+                    .path_and_query()
+                    .map(|path_and_query| path_and_query.as_str())
+                    .unwrap_or("/")
+            );
+
+            return Ok(HttpResponse::MovedPermanently()
+                .insert_header((header::LOCATION, location))
+                .finish());
+        }
+    };
+
+    let request_path = percent_decode_str(req.path())
+        .decode_utf8()
+        .map_err(|_| AppError::Validation("Invalid path".to_string()))?
+        .trim_start_matches('/')
+        .to_string();
+
+    let mut file = find_file(&mut conn, &site, &request_path)?;
+
+    if let Some((rule, target)) = find_redirect(&mut conn, &site, &request_path, file.is_some())? {
+        if rule.status == 200 {
+            // Rewrites serve the target without changing the URL
+            file = find_file(&mut conn, &site, target.trim_start_matches('/'))?;
+        } else {
+            let location = match req.query_string() {
+                "" => target,
+                query if !target.contains('?') => format!("{}?{}", target, query),
+                _ => target,
+            };
+            let redirect_status = if rule.status == 301 {
+                StatusCode::MOVED_PERMANENTLY
+            } else {
+                StatusCode::FOUND
+            };
+
+            return Ok(HttpResponse::build(redirect_status)
+                .insert_header((header::LOCATION, location))
+                .finish());
+        }
+    }
+
+    let (file, response_status) = match file {
+        Some(file) => (file, StatusCode::OK),
+        None => find_fallback(&mut conn, &site)?
+            .ok_or_else(|| AppError::NotFound("File not found".to_string()))?,
+    };
+
+    let response_headers = find_headers(&mut conn, &site, &request_path)?;
+    let encoding = find_encoding(&req, &file);
+    let key = match encoding {
+        Some(encoding) => encoding.variant_key(&file.path),
+        None => file.path.clone(),
+    };
+    let (size, body) = storage_client.fetch_file(&key).await?;
+
+    let mut response = HttpResponse::build(response_status);
+    response.content_type(file.mime_type.clone());
+    if let Some(file_cache_control) = &file.cache_control {
+        response.insert_header((header::CACHE_CONTROL, file_cache_control.clone()));
+    }
+    if !file.encodings().is_empty() {
+        response.insert_header((header::VARY, "Accept-Encoding"));
+    }
+    if let Some(encoding) = encoding {
+        response.insert_header((header::CONTENT_ENCODING, encoding.as_str()));
+    }
+    for response_header in response_headers {
+        response.insert_header(response_header);
+    }
+
+    Ok(response
+        .no_chunking(size)
+        .streaming(ReaderStream::new(body.into_async_read())))
+}
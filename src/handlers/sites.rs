@@ -1,19 +1,48 @@
 use std::collections::HashMap;
 
 use crate::db::DbPool;
-use crate::models::{File, Site};
-use crate::services::{dynamodb, s3};
-use crate::utils::zip::extract_file;
+use crate::error::AppError;
+use crate::handlers::deployments::{
+    config_of, files_to_reuse, publish_deployment, publish_routing, put_routing, redirect_item,
+    release_routing, resolve_index_file, resolve_not_found_file, routing_key, unpublish_config,
+    update_pages, DeploymentUpload, Manifest, PageSettings, DEFAULT_INDEX_FILE,
+};
+use crate::handlers::domains::{is_host_taken, site_hosts};
+use crate::middleware::auth::Caller;
+use crate::models::{Deployment, File, HeaderRule, HostRedirect, RedirectRule, Site};
+use crate::services::{cloudfront_key_value, dynamodb, storage::Storage};
+use crate::utils::archive::{extract_archive, normalize_path, strip_common_root};
+use crate::utils::content_type::{self, UploadPolicy};
+use crate::utils::deployment_config::DeploymentConfig;
+use crate::utils::hostname::HostPolicy;
 use actix_multipart::form::{tempfile::TempFile, text::Text, MultipartForm};
-use actix_web::{web, HttpResponse, Responder};
-use aws_sdk_dynamodb::types::AttributeValue;
-use chrono::Utc;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
 use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+/// Archives are extracted and uploaded without being buffered in memory,
+/// so the limit is only there to bound the disk space used by an upload.
+pub const MAX_ARCHIVE_FILE_SIZE: usize = 100 * 1024 * 1024; // 100MB
+
 enum SiteType {
     Html,
-    Zip,
+    /// A zip or tar archive, the format is detected from its content
+    Archive,
+}
+
+impl SiteType {
+    fn parse(value: &str) -> Result<SiteType, AppError> {
+        match value {
+            "html" => Ok(SiteType::Html),
+            "zip" | "tar" | "tar.gz" | "tar.zst" => Ok(SiteType::Archive),
+            _ => Err(AppError::Validation(
+                "Invalid site type. Only 'html', 'zip', 'tar', 'tar.gz' and 'tar.zst' are allowed"
+                    .to_string(),
+            )),
+        }
+    }
 }
 
 #[derive(MultipartForm)]
@@ -22,384 +51,1145 @@ pub struct CreateSiteForm {
     suffix: Text<String>,
 
     site_type: Text<String>,
-    index_file: Text<String>,
+    /// Defaults to the current index of the site, then `index.html`
+    index_file: Option<Text<String>>,
+
+    /// Serves the index file for unknown paths, defaults to the current mode of the site
+    spa: Option<Text<bool>>,
+    /// Served with a 404 for unknown paths of non-SPA sites, defaults to
+    /// the current one of the site, then `404.html` if uploaded
+    not_found_file: Option<Text<String>>,
+
+    /// Strips the top-level directory when every file is inside the same one
+    strip_root: Option<Text<bool>>,
+
+    /// JSON manifest of every file of the deployment, files which aren't
+    /// uploaded are reused from the active deployment if unchanged
+    manifest: Option<Text<String>>,
 
     #[multipart(rename = "file")]
     files: Vec<TempFile>,
 }
 
-fn validate_files(site_type: SiteType, files: Vec<TempFile>) -> Result<Vec<TempFile>, String> {
+async fn validate_files(
+    site_type: SiteType,
+    policy: &UploadPolicy,
+    mut files: Vec<TempFile>,
+) -> Result<Vec<TempFile>, AppError> {
     match site_type {
         SiteType::Html => {
             const MAX_FILE_SIZE: usize = 2 * 1024 * 1024; // 2MB
 
-            for file in &files {
-                let content_type = file.content_type.clone().ok_or_else(|| "")?;
-                if content_type != "text/html" && content_type != "text/css" {
-                    return Err(
-                        "Invalid file type. Only text/html and text/css files are allowed"
-                            .to_string(),
-                    );
-                }
+            for file in &mut files {
+                // File names become storage keys, so they can't leave the site prefix
+                let file_name = file
+                    .file_name
+                    .as_deref()
+                    .ok_or_else(|| AppError::Validation("File name is missing".to_string()))?;
+                let file_name = normalize_path(file_name)?;
 
                 if file.size > MAX_FILE_SIZE {
-                    return Err("File size is too large. Maximum size is 2MB".to_string());
+                    return Err(AppError::Validation(
+                        "File size is too large. Maximum size is 2MB".to_string(),
+                    ));
                 }
+
+                // The content type sent by the client isn't trusted
+                let content_type = content_type::detect(&file_name, file.file.path())?;
+                policy.html.check(&file_name, &content_type)?;
+
+                file.content_type = Some(content_type);
+                file.file_name = Some(file_name);
             }
 
             Ok(files)
         }
-        SiteType::Zip => {
-            // If the site type is zip, we will take the first file and check if it's a zip file
-            // if it's not a zip file, we will return an error
+        SiteType::Archive => {
+            // If the site type is an archive, we will take the first file and check if it's
+            // a supported archive, if it's not, we will return an error
             // otherwise extract the files and add them to the updated_files vector
             let first_file = match files.into_iter().next() {
                 Some(file) => file,
                 None => {
-                    return Err("No files found".to_string());
+                    return Err(AppError::Validation("No files found".to_string()));
                 }
             };
 
-            let content_type = first_file.content_type.clone().ok_or_else(|| "")?;
-            if content_type != "application/zip" {
-                return Err("Invalid file type. Only zip files are allowed".to_string());
+            if first_file.size > MAX_ARCHIVE_FILE_SIZE {
+                return Err(AppError::Validation(format!(
+                    "Archive size is too large. Maximum size is {}MB",
+                    MAX_ARCHIVE_FILE_SIZE / 1024 / 1024
+                )));
             }
 
-            const MAX_ZIP_FILE_SIZE: usize = 5 * 1024 * 1024; // 5MB
-            if first_file.size > MAX_ZIP_FILE_SIZE {
-                return Err("Zip file size is too large. Maximum size is 5MB".to_string());
+            // Extraction reads and writes up to the uncompressed size limit,
+            // so it runs on the blocking thread pool
+            let archive_file = first_file.file.into_file();
+            let files = tokio::task::spawn_blocking(move || extract_archive(archive_file))
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))??;
+            for file in &files {
+                if let (Some(file_name), Some(content_type)) = (&file.file_name, &file.content_type)
+                {
+                    policy.archive.check(file_name, content_type)?;
+                }
             }
 
-            let files = extract_file(first_file.file.into_file());
             Ok(files)
         }
     }
 }
 
+/// Loads a site the caller is allowed to manage, admins can manage every site.
+pub fn find_site(
+    conn: &mut SqliteConnection,
+    site_id_to_find: &str,
+    caller: &Caller,
+) -> Result<Site, AppError> {
+    use crate::schema::sites::dsl::*;
+
+    let mut query = sites.filter(id.eq(site_id_to_find)).into_boxed();
+    if !caller.is_admin {
+        query = query.filter(owner_id.eq(caller.user_id.clone()));
+    }
+
+    query
+        .select(Site::as_select())
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("Site not found".to_string()))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn create_site(
     pool: web::Data<DbPool>,
-    s3_client: web::Data<s3::Client>,
+    caller: web::ReqData<Caller>,
+    storage_client: web::Data<dyn Storage>,
     dynamodb_client: web::Data<dynamodb::Client>,
+    kvs_client: web::Data<cloudfront_key_value::Client>,
+    upload_policy: web::Data<UploadPolicy>,
+    host_policy: web::Data<HostPolicy>,
     MultipartForm(form): MultipartForm<CreateSiteForm>,
-) -> impl Responder {
-    use crate::schema::files::dsl::*;
-    use crate::schema::sites::dsl::*;
-
-    let site_type = match form.site_type.clone().as_str() {
-        "html" => SiteType::Html,
-        "zip" => SiteType::Zip,
-        _ => {
-            return HttpResponse::BadRequest().json(json!({
-                "message": "Invalid site type. Only 'html' and 'zip' are allowed",
-            }));
-        }
+) -> Result<HttpResponse, AppError> {
+    // Validated before the files, which can take a while to extract
+    let formatted_host = host_policy.site_host(form.domain.as_str(), form.suffix.as_str())?;
+    let site_type = SiteType::parse(form.site_type.as_str())?;
+    let mut uploading_files = validate_files(site_type, &upload_policy, form.files).await?;
+    let stripped_prefix = match form.strip_root.as_deref() {
+        Some(true) => strip_common_root(&mut uploading_files),
+        _ => None,
     };
+    let config = DeploymentConfig::take(&mut uploading_files)?;
+    let (redirect_count, header_count) = (config.redirects.len(), config.headers.len());
 
-    let uploading_files = match validate_files(site_type, form.files) {
-        Ok(updated_files) => updated_files,
-        Err(message) => {
-            return HttpResponse::BadRequest().json(json!({
-                "message": message,
-            }));
-        }
-    };
+    let file_names: Vec<&str> = uploading_files
+        .iter()
+        .filter_map(|file| file.file_name.as_deref())
+        .collect();
+    let site_index_file = resolve_index_file(
+        form.index_file.as_ref().map(|requested| requested.as_str()),
+        None,
+        &file_names,
+    )?;
+    let site_spa = form.spa.as_deref().copied().unwrap_or(false);
+    let site_not_found_file = resolve_not_found_file(
+        form.not_found_file
+            .as_ref()
+            .map(|requested| requested.as_str()),
+        None,
+        site_spa,
+        &file_names,
+    )?;
 
-    let mut conn = pool.get().expect("couldn't get db connection from pool");
+    let mut conn = pool.get()?;
 
     // Check if the host is already taken
     // If it is, return an error
-    let formatted_host = format!("{}{}", form.domain.clone(), form.suffix.clone());
-    match sites
-        .filter(host.eq(formatted_host.clone()))
-        .select(Site::as_select())
-        .first(&mut conn)
-    {
-        Ok(_) => {
-            return HttpResponse::BadRequest().json(json!({
-                "message": "Domain is already taken",
-            }));
-        }
-        Err(_) => false,
-    };
+    if is_host_taken(&mut conn, &formatted_host, None)? {
+        return Err(AppError::Conflict("Domain is already taken".to_string()));
+    }
 
     let now = Utc::now().naive_utc();
     let new_site = Site {
         id: ulid::Ulid::new().to_string(),
         host: formatted_host.clone(),
-        index_file: Some(form.index_file.clone()),
+        index_file: Some(site_index_file.clone()),
+        active_deployment_id: None,
+        owner_id: Some(caller.user_id.clone()),
+        spa: site_spa,
+        not_found_file: site_not_found_file.clone(),
+        title: None,
+        description: None,
+        tags: String::new(),
         created_at: now,
         updated_at: now,
     };
 
-    diesel::insert_into(sites)
-        .values(&new_site)
-        .execute(&mut conn)
-        .expect("Error saving new site");
-
-    let site_path = format!("sites/{}/", new_site.id.clone());
-    let uploaded_files = &s3_client
-        .upload_files(uploading_files, &site_path)
-        .await
-        .expect("Error uploading files");
+    publish_deployment(
+        &mut conn,
+        storage_client.get_ref(),
+        &dynamodb_client,
+        &kvs_client,
+        &new_site,
+        true,
+        DeploymentUpload {
+            files: uploading_files,
+            reused_files: Vec::new(),
+            pages: PageSettings {
+                index_file: site_index_file,
+                spa: site_spa,
+                not_found_file: site_not_found_file,
+            },
+            config,
+        },
+    )
+    .await
+    // The host was claimed by another request since it was checked
+    .map_err(|e| match e {
+        AppError::Conflict(_) => AppError::Conflict("Domain is already taken".to_string()),
+        e => e,
+    })?;
 
-    let new_files: Vec<File> = uploaded_files
-        .into_iter()
-        .map(|file| {
-            let now = Utc::now().naive_utc();
-            let file_name = file.filename.clone();
-            let file_path = format!("{}{}", site_path, file_name);
-            let file_mime_type = file.content_type.clone();
-            File {
-                id: ulid::Ulid::new().to_string(),
-                site_id: new_site.id.clone(),
-                name: file_name.clone(),
-                path: file_path,
-                mime_type: file_mime_type,
-                size: file.size,
-                is_index: file_name == form.index_file.clone(),
-                created_at: now,
-                updated_at: now,
-            }
-        })
-        .collect();
-
-    diesel::insert_into(files)
-        .values(&new_files)
-        .execute(&mut conn)
-        .expect("Error saving new files");
-
-    let mut dynamodb_values: HashMap<String, AttributeValue> = HashMap::new();
-    dynamodb_values.insert(
-        "host".to_string(),
-        AttributeValue::S(formatted_host.clone()),
-    );
-    dynamodb_values.insert("siteId".to_string(), AttributeValue::S(new_site.id.clone()));
-    dynamodb_values.insert(
-        "cacheKey".to_string(),
-        AttributeValue::S(format!(
-            "{}=x={}",
-            new_site.id.clone(),
-            Utc::now().timestamp()
-        )),
-    );
-    dynamodb_values.insert(
-        "timestamp".to_string(),
-        AttributeValue::N(Utc::now().timestamp().to_string()),
-    );
-
-    dynamodb_client
-        .put_item(dynamodb_values)
-        .await
-        .expect("Error putting item");
-
-    HttpResponse::Ok().json(json!({
-        "message": format!("You can now access your site at: https://{} with site id: {}", new_site.host, new_site.id)
-    }))
+    Ok(HttpResponse::Ok().json(json!({
+        "message": format!("You can now access your site at: https://{} with site id: {}", new_site.host, new_site.id),
+        "site_id": new_site.id,
+        "stripped_prefix": stripped_prefix,
+        "redirect_rules": redirect_count,
+        "header_rules": header_count,
+    })))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn update_site(
     path_data: web::Path<String>,
     pool: web::Data<DbPool>,
-    s3_client: web::Data<s3::Client>,
+    caller: web::ReqData<Caller>,
+    storage_client: web::Data<dyn Storage>,
     dynamodb_client: web::Data<dynamodb::Client>,
+    kvs_client: web::Data<cloudfront_key_value::Client>,
+    upload_policy: web::Data<UploadPolicy>,
     MultipartForm(form): MultipartForm<CreateSiteForm>,
-) -> impl Responder {
-    use crate::schema::files::dsl::{site_id as file_site_id, *};
-    use crate::schema::sites::dsl::{id as site_id, *};
+) -> Result<HttpResponse, AppError> {
+    let manifest: Option<Manifest> = match &form.manifest {
+        Some(manifest) => Some(
+            serde_json::from_str(manifest.as_str())
+                .map_err(|e| AppError::Validation(format!("Invalid manifest: {}", e)))?,
+        ),
+        None => None,
+    };
 
-    let site_type = match form.site_type.clone().as_str() {
-        "html" => SiteType::Html,
-        "zip" => SiteType::Zip,
-        _ => {
-            return HttpResponse::BadRequest().json(json!({
-                "message": "Invalid site type. Only 'html' and 'zip' are allowed",
-            }));
+    // The site is looked up before the upload is extracted, so callers
+    // can't make the server extract archives for sites they don't own
+    let site_id_to_update = path_data.into_inner();
+    let mut conn = pool.get()?;
+
+    let site = find_site(&mut conn, &site_id_to_update, &caller)?;
+
+    // With a manifest, nothing has to be uploaded if every file is unchanged
+    let site_type = SiteType::parse(form.site_type.as_str())?;
+    let mut uploading_files = if manifest.is_some() && form.files.is_empty() {
+        Vec::new()
+    } else {
+        validate_files(site_type, &upload_policy, form.files).await?
+    };
+    let stripped_prefix = match form.strip_root.as_deref() {
+        Some(true) => strip_common_root(&mut uploading_files),
+        _ => None,
+    };
+
+    let reused_files = match &manifest {
+        Some(manifest) => {
+            let uploaded_names: Vec<String> = uploading_files
+                .iter()
+                .filter_map(|file| file.file_name.clone())
+                .collect();
+            files_to_reuse(&mut conn, &site, manifest, &uploaded_names)?
         }
+        None => Vec::new(),
     };
+    let config = DeploymentConfig::take(&mut uploading_files)?;
+    let (redirect_count, header_count) = (config.redirects.len(), config.headers.len());
+
+    let file_names: Vec<&str> = uploading_files
+        .iter()
+        .filter_map(|file| file.file_name.as_deref())
+        .chain(reused_files.iter().map(|file| file.name.as_str()))
+        .collect();
+    let index_file = resolve_index_file(
+        form.index_file.as_ref().map(|requested| requested.as_str()),
+        site.index_file.as_deref(),
+        &file_names,
+    )?;
+    let spa = form.spa.as_deref().copied().unwrap_or(site.spa);
+    let not_found_file = resolve_not_found_file(
+        form.not_found_file
+            .as_ref()
+            .map(|requested| requested.as_str()),
+        site.not_found_file.as_deref(),
+        spa,
+        &file_names,
+    )?;
+
+    // Previous deployments are kept so the site can be rolled back
+    let deployment = publish_deployment(
+        &mut conn,
+        storage_client.get_ref(),
+        &dynamodb_client,
+        &kvs_client,
+        &site,
+        false,
+        DeploymentUpload {
+            files: uploading_files,
+            reused_files,
+            pages: PageSettings {
+                index_file,
+                spa,
+                not_found_file,
+            },
+            config,
+        },
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": format!("Site updated successfully"),
+        "deployment_id": deployment.id,
+        "stripped_prefix": stripped_prefix,
+        "redirect_rules": redirect_count,
+        "header_rules": header_count,
+    })))
+}
 
-    let uploading_files = match validate_files(site_type, form.files) {
-        Ok(updated_files) => updated_files,
-        Err(message) => {
-            return HttpResponse::BadRequest().json(json!({
-                "message": message,
-            }));
+/// Longest period during which the previous host of a site redirects to the new one.
+const MAX_REDIRECT_DAYS: u32 = 365;
+
+#[derive(Deserialize)]
+pub struct PatchSiteBody {
+    /// New name of the site, defaults to the current one
+    domain: Option<String>,
+    /// New suffix of the site, defaults to the current one
+    suffix: Option<String>,
+    /// Days during which the previous host redirects to the new one with a
+    /// 301, it is released right away when 0
+    #[serde(default)]
+    redirect_days: u32,
+    /// Must be a file of the active deployment
+    index_file: Option<String>,
+    spa: Option<bool>,
+    /// Cleared when empty
+    title: Option<String>,
+    /// Cleared when empty
+    description: Option<String>,
+    /// Replace the current tags
+    tags: Option<Vec<String>>,
+}
+
+const MAX_TITLE_LENGTH: usize = 255;
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+const MAX_TAGS: usize = 20;
+const MAX_TAG_LENGTH: usize = 32;
+
+/// Trims a title or description, empty values clearing it.
+fn metadata_text(
+    field: &str,
+    value: Option<&str>,
+    current: Option<&str>,
+    max_length: usize,
+) -> Result<Option<String>, AppError> {
+    let Some(value) = value else {
+        return Ok(current.map(str::to_string));
+    };
+
+    let value = value.trim();
+    if value.chars().count() > max_length {
+        return Err(AppError::Validation(format!(
+            "The {} is at most {} characters",
+            field, max_length
+        )));
+    }
+
+    Ok((!value.is_empty()).then(|| value.to_string()))
+}
+
+/// Lowercases and deduplicates tags, returned comma separated as they are stored.
+fn normalize_tags(tags: &[String]) -> Result<String, AppError> {
+    let mut normalized_tags: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty()
+            || tag.chars().count() > MAX_TAG_LENGTH
+            || !tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(AppError::Validation(format!(
+                "Invalid tag {}: Tags are 1 to {} letters, digits, hyphens or underscores",
+                tag, MAX_TAG_LENGTH
+            )));
         }
+        if !normalized_tags.contains(&tag) {
+            normalized_tags.push(tag);
+        }
+    }
+
+    if normalized_tags.len() > MAX_TAGS {
+        return Err(AppError::Validation(format!(
+            "A site has at most {} tags",
+            MAX_TAGS
+        )));
+    }
+
+    Ok(normalized_tags.join(","))
+}
+
+/// Page settings and metadata a site is left with by a `PATCH`.
+struct SiteChanges {
+    pages: PageSettings,
+    title: Option<String>,
+    description: Option<String>,
+    tags: String,
+}
+
+/// Validates the page settings and metadata of the body against the site and
+/// the files of its active deployment (`None` until it is deployed). Nothing
+/// is changed, so an invalid body is rejected before the site is moved.
+fn resolve_changes(
+    site: &Site,
+    body: &PatchSiteBody,
+    file_names: Option<&[&str]>,
+) -> Result<SiteChanges, AppError> {
+    let title = metadata_text(
+        "title",
+        body.title.as_deref(),
+        site.title.as_deref(),
+        MAX_TITLE_LENGTH,
+    )?;
+    let description = metadata_text(
+        "description",
+        body.description.as_deref(),
+        site.description.as_deref(),
+        MAX_DESCRIPTION_LENGTH,
+    )?;
+    let tags = match &body.tags {
+        Some(requested_tags) => normalize_tags(requested_tags)?,
+        None => site.tags.clone(),
     };
 
-    let site_id_to_update = path_data.into_inner();
-    let mut conn = pool.get().expect("couldn't get db connection from pool");
+    let new_spa = body.spa.unwrap_or(site.spa);
+    let pages = match file_names {
+        Some(file_names) => PageSettings {
+            index_file: resolve_index_file(
+                body.index_file.as_deref(),
+                site.index_file.as_deref(),
+                file_names,
+            )?,
+            spa: new_spa,
+            not_found_file: resolve_not_found_file(
+                None,
+                site.not_found_file.as_deref(),
+                new_spa,
+                file_names,
+            )?,
+        },
+        // Nothing to check against until the site is deployed
+        None => PageSettings {
+            index_file: body
+                .index_file
+                .clone()
+                .or(site.index_file.clone())
+                .unwrap_or_else(|| DEFAULT_INDEX_FILE.to_string()),
+            spa: new_spa,
+            not_found_file: site.not_found_file.clone().filter(|_| !new_spa),
+        },
+    };
 
-    let site: Site = match sites
-        .filter(site_id.eq(site_id_to_update.clone()))
-        .select(Site::as_select())
-        .first(&mut conn)
-    {
-        Ok(site) => site,
-        Err(_) => {
-            return HttpResponse::NotFound().finish();
+    Ok(SiteChanges {
+        pages,
+        title,
+        description,
+        tags,
+    })
+}
+
+/// Applies resolved changes to a site. The routing items of its hosts are put
+/// again with the new settings, which refreshes their `cacheKey`; the stored
+/// files are left untouched, so headers stored with the objects for the
+/// directory of the index file (e.g. `_headers` rules for `/`) keep following
+/// the previous index file until the next deployment.
+async fn apply_changes(
+    conn: &mut SqliteConnection,
+    dynamodb_client: &dynamodb::Client,
+    site: &Site,
+    deployment: Option<&Deployment>,
+    changes: SiteChanges,
+) -> Result<Site, AppError> {
+    use crate::schema::sites::dsl::{
+        description, id, index_file, not_found_file, sites, spa, tags, title, updated_at,
+    };
+
+    let SiteChanges {
+        pages,
+        title: new_title,
+        description: new_description,
+        tags: new_tags,
+    } = changes;
+
+    let hosts = site_hosts(conn, site)?;
+    if let Some(deployment) = deployment {
+        let updated_deployment = Deployment {
+            id: deployment.id.clone(),
+            site_id: deployment.site_id.clone(),
+            status: deployment.status.clone(),
+            file_count: deployment.file_count,
+            total_size: deployment.total_size,
+            index_file: Some(pages.index_file.clone()),
+            spa: pages.spa,
+            not_found_file: pages.not_found_file.clone(),
+            created_at: deployment.created_at,
+            updated_at: deployment.updated_at,
+        };
+        publish_routing(
+            dynamodb_client,
+            &hosts,
+            &updated_deployment,
+            Some(deployment),
+        )
+        .await?;
+    }
+
+    let updated = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        if let Some(deployment) = deployment {
+            update_pages(conn, deployment, &pages)?;
+        }
+
+        diesel::update(sites.filter(id.eq(site.id.clone())))
+            .set((
+                index_file.eq(Some(pages.index_file.clone())),
+                spa.eq(pages.spa),
+                not_found_file.eq(pages.not_found_file.clone()),
+                title.eq(new_title),
+                description.eq(new_description),
+                tags.eq(new_tags),
+                updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        sites
+            .filter(id.eq(site.id.clone()))
+            .select(Site::as_select())
+            .first(conn)
+    });
+
+    match updated {
+        Ok(updated_site) => Ok(updated_site),
+        Err(e) => {
+            if let Some(deployment) = deployment {
+                for site_host in &hosts {
+                    if let Err(restore_error) =
+                        put_routing(dynamodb_client, site_host, deployment).await
+                    {
+                        println!(
+                            "Error restoring the routing of {}: {}",
+                            site_host, restore_error
+                        );
+                    }
+                }
+            }
+            Err(e.into())
+        }
+    }
+}
+
+/// Moves a site to `new_host`. The new host is claimed in the routing table
+/// before the previous one is released (or turned into a redirect) and the
+/// site row updated, each step being undone if a later one fails.
+async fn move_site(
+    conn: &mut SqliteConnection,
+    dynamodb_client: &dynamodb::Client,
+    site: &Site,
+    new_host: &str,
+    redirect_days: u32,
+) -> Result<Site, AppError> {
+    use crate::schema::deployments::dsl::{deployments, id as deployments_id};
+    use crate::schema::host_redirects::dsl::{
+        host as redirect_host, host_redirects, site_id as redirect_site_id,
+    };
+    use crate::schema::sites::dsl::{host, id, sites, updated_at};
+
+    if is_host_taken(conn, new_host, Some(&site.id))? {
+        return Err(AppError::Conflict("Domain is already taken".to_string()));
+    }
+
+    let deployment: Option<Deployment> = match &site.active_deployment_id {
+        Some(active_deployment_id) => deployments
+            .filter(deployments_id.eq(active_deployment_id))
+            .select(Deployment::as_select())
+            .first(conn)
+            .optional()?,
+        None => None,
+    };
+    let now = Utc::now().naive_utc();
+    let expires_at = (redirect_days > 0).then(|| now + Duration::days(redirect_days as i64));
+
+    if let Some(deployment) = &deployment {
+        put_routing(dynamodb_client, new_host, deployment).await?;
+    }
+    let undo_claim = || async {
+        if let Err(e) = release_routing(dynamodb_client, new_host, &site.id).await {
+            println!("Error releasing the routing of {}: {}", new_host, e);
         }
     };
 
-    let site_path = format!("sites/{}/", site.id.clone());
-    let uploaded_files = &s3_client
-        .upload_files(uploading_files, &site_path)
-        .await
-        .expect("Error uploading files");
+    let released = match expires_at {
+        Some(expires_at) => {
+            dynamodb_client
+                .claim_item(
+                    redirect_item(&site.host, &site.id, new_host, expires_at),
+                    "host",
+                    "siteId",
+                )
+                .await
+        }
+        None => release_routing(dynamodb_client, &site.host, &site.id).await,
+    };
+    if let Err(e) = released {
+        undo_claim().await;
+        return Err(e);
+    }
 
-    diesel::delete(files.filter(file_site_id.eq(site.id.clone())))
-        .execute(&mut conn)
-        .expect("Error deleting old files");
+    let moved = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        // Moving back to a previous host ends its redirect
+        diesel::delete(host_redirects.filter(redirect_host.eq(new_host))).execute(conn)?;
+        if let Some(expires_at) = expires_at {
+            diesel::insert_into(host_redirects)
+                .values(HostRedirect {
+                    id: ulid::Ulid::new().to_string(),
+                    site_id: site.id.clone(),
+                    host: site.host.clone(),
+                    expires_at,
+                    created_at: now,
+                    updated_at: now,
+                })
+                .execute(conn)?;
+        }
 
-    let new_files: Vec<File> = uploaded_files
-        .into_iter()
-        .map(|file| {
-            let now = Utc::now().naive_utc();
-            let file_name = file.filename.clone();
-            let file_path = format!("{}{}", site_path, file_name);
-            let file_mime_type = file.content_type.clone();
-            File {
-                id: ulid::Ulid::new().to_string(),
-                site_id: site.id.clone(),
-                name: file_name.clone(),
-                path: file_path,
-                mime_type: file_mime_type,
-                size: file.size,
-                is_index: file_name == form.index_file.clone(),
-                created_at: now,
-                updated_at: now,
+        diesel::update(sites.filter(id.eq(site.id.clone())))
+            .set((host.eq(new_host), updated_at.eq(now)))
+            .execute(conn)?;
+        sites
+            .filter(id.eq(site.id.clone()))
+            .select(Site::as_select())
+            .first(conn)
+    });
+
+    let moved_site = match moved {
+        Ok(moved_site) => moved_site,
+        Err(e) => {
+            let restored = match &deployment {
+                Some(deployment) => put_routing(dynamodb_client, &site.host, deployment).await,
+                None => release_routing(dynamodb_client, &site.host, &site.id).await,
+            };
+            if let Err(restore_error) = restored {
+                println!(
+                    "Error restoring the routing of {}: {}",
+                    site.host, restore_error
+                );
             }
-        })
-        .collect();
+            undo_claim().await;
+            return Err(e.into());
+        }
+    };
+
+    // Earlier hosts of the site now redirect to the new one
+    let previous_redirects: Vec<HostRedirect> = host_redirects
+        .filter(redirect_site_id.eq(site.id.clone()))
+        .filter(redirect_host.ne(site.host.clone()))
+        .select(HostRedirect::as_select())
+        .load(conn)?;
+    for previous_redirect in previous_redirects {
+        let item = redirect_item(
+            &previous_redirect.host,
+            &site.id,
+            new_host,
+            previous_redirect.expires_at,
+        );
+        if let Err(e) = dynamodb_client.claim_item(item, "host", "siteId").await {
+            println!(
+                "Error updating the redirect of {}: {}",
+                previous_redirect.host, e
+            );
+        }
+    }
+
+    Ok(moved_site)
+}
+
+/// Updates the settings of a site which don't need a new deployment: its
+/// host, how its pages are served and its metadata.
+pub async fn patch_site(
+    path_data: web::Path<String>,
+    pool: web::Data<DbPool>,
+    caller: web::ReqData<Caller>,
+    dynamodb_client: web::Data<dynamodb::Client>,
+    host_policy: web::Data<HostPolicy>,
+    body: web::Json<PatchSiteBody>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::deployments::dsl::{deployments, id as deployments_id};
+    use crate::schema::files::dsl::{deployment_id, files, name};
+
+    let site_id_to_update = path_data.into_inner();
+    let mut conn = pool.get()?;
+
+    let mut site = find_site(&mut conn, &site_id_to_update, &caller)?;
+
+    if body.redirect_days > MAX_REDIRECT_DAYS {
+        return Err(AppError::Validation(format!(
+            "Redirects last at most {} days",
+            MAX_REDIRECT_DAYS
+        )));
+    }
+
+    let previous_host = site.host.clone();
+    let new_host = match (&body.domain, &body.suffix) {
+        (None, None) => site.host.clone(),
+        _ => {
+            let (current_name, current_suffix) =
+                site.host.split_once('.').unwrap_or((&site.host, ""));
+            host_policy.site_host(
+                body.domain.as_deref().unwrap_or(current_name),
+                body.suffix.as_deref().unwrap_or(current_suffix),
+            )?
+        }
+    };
+
+    // Every field is validated before the site is moved, so a rejected body
+    // leaves the site as it was
+    let deployment: Option<Deployment> = match &site.active_deployment_id {
+        Some(active_deployment_id) => deployments
+            .filter(deployments_id.eq(active_deployment_id))
+            .select(Deployment::as_select())
+            .first(&mut conn)
+            .optional()?,
+        None => None,
+    };
+    let file_names: Option<Vec<String>> = match &deployment {
+        Some(deployment) => Some(
+            files
+                .filter(deployment_id.eq(deployment.id.clone()))
+                .select(name)
+                .load(&mut conn)?,
+        ),
+        None => None,
+    };
+    let file_names: Option<Vec<&str>> = file_names
+        .as_ref()
+        .map(|file_names| file_names.iter().map(String::as_str).collect());
+    let changes = resolve_changes(&site, &body, file_names.as_deref())?;
 
-    diesel::insert_into(files)
-        .values(&new_files)
-        .execute(&mut conn)
-        .expect("Error saving new files");
-
-    let mut dynamodb_values: HashMap<String, AttributeValue> = HashMap::new();
-    dynamodb_values.insert("host".to_string(), AttributeValue::S(site.host.clone()));
-    dynamodb_values.insert("siteId".to_string(), AttributeValue::S(site.id.clone()));
-    dynamodb_values.insert(
-        "cacheKey".to_string(),
-        AttributeValue::S(format!("{}=x={}", site.id.clone(), Utc::now().timestamp())),
-    );
-    dynamodb_values.insert(
-        "timestamp".to_string(),
-        AttributeValue::N(Utc::now().timestamp().to_string()),
-    );
-
-    dynamodb_client
-        .put_item(dynamodb_values)
-        .await
-        .expect("Error putting item");
-
-    HttpResponse::Ok().json(json!({
-        "message": format!("Site updated successfully")
-    }))
+    if new_host != site.host {
+        site = move_site(
+            &mut conn,
+            &dynamodb_client,
+            &site,
+            &new_host,
+            body.redirect_days,
+        )
+        .await?;
+    }
+
+    let site = apply_changes(
+        &mut conn,
+        &dynamodb_client,
+        &site,
+        deployment.as_ref(),
+        changes,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "site": site,
+        "previous_host": (previous_host != site.host).then_some(previous_host),
+    })))
 }
 
 pub async fn delete_site(
     path_data: web::Path<String>,
     pool: web::Data<DbPool>,
-    s3_client: web::Data<s3::Client>,
+    caller: web::ReqData<Caller>,
+    storage_client: web::Data<dyn Storage>,
     dynamodb_client: web::Data<dynamodb::Client>,
-) -> impl Responder {
+    kvs_client: web::Data<cloudfront_key_value::Client>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::deployments::dsl::{
+        deployments, id as deployments_id, site_id as deployment_site_id,
+    };
+    use crate::schema::domains::dsl::{domains, site_id as domain_site_id};
     use crate::schema::files::dsl::{site_id as file_site_id, *};
+    use crate::schema::header_rules::dsl::{header_rules, site_id as header_site_id};
+    use crate::schema::host_redirects::dsl::{
+        host as redirect_host, host_redirects, site_id as redirect_site_id,
+    };
+    use crate::schema::redirect_rules::dsl::{redirect_rules, site_id as rule_site_id};
     use crate::schema::sites::dsl::{id as site_id, *};
 
     let site_id_to_delete = path_data.into_inner();
-    let mut conn = pool.get().expect("couldn't get db connection from pool");
+    let mut conn = pool.get()?;
 
-    let site: Site = match sites
-        .filter(site_id.eq(site_id_to_delete.clone()))
-        .select(Site::as_select())
-        .first(&mut conn)
-    {
-        Ok(site) => site,
-        Err(_) => {
-            return HttpResponse::NotFound().finish();
-        }
-    };
+    let site = find_site(&mut conn, &site_id_to_delete, &caller)?;
 
-    let file_paths: Vec<String> = files
+    let file_keys: Vec<String> = files
         .filter(file_site_id.eq(site.id.clone()))
-        .select(path)
-        .load::<String>(&mut conn)
-        .expect("Error loading files");
-
-    match s3_client.delete_files(file_paths).await {
-        true => (),
-        false => {
-            return HttpResponse::InternalServerError().finish();
-        }
+        .select(File::as_select())
+        .load(&mut conn)?
+        .iter()
+        .flat_map(File::storage_keys)
+        .collect();
+
+    storage_client.delete_files(file_keys).await?;
+
+    for site_host in site_hosts(&mut conn, &site)? {
+        dynamodb_client.delete_item(routing_key(&site_host)).await?;
+    }
+
+    let redirect_hosts: Vec<String> = host_redirects
+        .filter(redirect_site_id.eq(site.id.clone()))
+        .select(redirect_host)
+        .load::<String>(&mut conn)?;
+    for previous_host in redirect_hosts {
+        release_routing(&dynamodb_client, &previous_host, &site.id).await?;
     }
 
-    let mut dynamodb_values: HashMap<String, AttributeValue> = HashMap::new();
-    dynamodb_values.insert("host".to_string(), AttributeValue::S(site.host.clone()));
+    let deployment_ids: Vec<String> = deployments
+        .filter(deployment_site_id.eq(site.id.clone()))
+        .select(deployments_id)
+        .load::<String>(&mut conn)?;
+    let deployment_configs = deployment_ids
+        .into_iter()
+        .map(|deployment_id_to_delete| {
+            let config = config_of(&mut conn, &deployment_id_to_delete)?;
+            Ok((deployment_id_to_delete, config))
+        })
+        .collect::<QueryResult<Vec<_>>>()?;
+    unpublish_config(&kvs_client, deployment_configs).await?;
+
+    diesel::delete(domains.filter(domain_site_id.eq(site.id.clone()))).execute(&mut conn)?;
+
+    diesel::delete(host_redirects.filter(redirect_site_id.eq(site.id.clone())))
+        .execute(&mut conn)?;
 
-    dynamodb_client
-        .delete_item(dynamodb_values)
-        .await
-        .expect("Error deleting item");
+    diesel::delete(redirect_rules.filter(rule_site_id.eq(site.id.clone()))).execute(&mut conn)?;
 
-    diesel::delete(files.filter(file_site_id.eq(site.id.clone())))
-        .execute(&mut conn)
-        .expect("Error deleting files");
+    diesel::delete(header_rules.filter(header_site_id.eq(site.id.clone()))).execute(&mut conn)?;
 
-    diesel::delete(sites.filter(site_id.eq(site.id.clone())))
-        .execute(&mut conn)
-        .expect("Error deleting site");
+    diesel::delete(files.filter(file_site_id.eq(site.id.clone()))).execute(&mut conn)?;
 
-    HttpResponse::Ok().json(json!({
+    diesel::delete(deployments.filter(deployment_site_id.eq(site.id.clone())))
+        .execute(&mut conn)?;
+
+    diesel::delete(sites.filter(site_id.eq(site.id.clone()))).execute(&mut conn)?;
+
+    Ok(HttpResponse::Ok().json(json!({
         "message": format!("Site deleted successfully")
-    }))
+    })))
 }
 
-pub async fn list_sites(pool: web::Data<DbPool>) -> impl Responder {
-    use crate::schema::sites::dsl::*;
+/// Sites returned by `list_sites` when no limit is given, and the largest limit.
+const DEFAULT_SITES_LIMIT: i64 = 50;
+const MAX_SITES_LIMIT: i64 = 200;
 
-    let mut conn = pool.get().expect("couldn't get db connection from pool");
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum SiteSort {
+    CreatedAt,
+    UpdatedAt,
+}
 
-    let sites_list: Vec<Site> = sites
-        .select(Site::as_select())
-        .load::<Site>(&mut conn)
-        .expect("Error loading sites");
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum SortOrder {
+    Asc,
+    Desc,
+}
 
-    HttpResponse::Ok().json(serde_json::json!({
-        "sites": sites_list,
-        "total": sites_list.len(),
-    }))
+#[derive(Deserialize)]
+pub struct ListSitesQuery {
+    /// Id of the last site of the previous page
+    cursor: Option<String>,
+    limit: Option<i64>,
+    /// Prefix of the host of the sites
+    host: Option<String>,
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+    /// Defaults to the newest sites first
+    sort: Option<SiteSort>,
+    order: Option<SortOrder>,
 }
 
-pub async fn get_site(path_data: web::Path<String>, pool: web::Data<DbPool>) -> impl Responder {
-    use crate::schema::files::dsl::{files, site_id as file_site_id};
+/// A site along with the size of its active deployment.
+#[derive(Serialize)]
+struct SiteSummary {
+    #[serde(flatten)]
+    site: Site,
+    file_count: i64,
+    total_size: i64,
+}
+
+/// Escapes the wildcards of a `LIKE` pattern, `\\` being the escape character.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Lists the sites of the caller a page at a time. Pages are ordered by
+/// `created_at` or `updated_at`, ties being broken by id, and the cursor is the
+/// id of the last site of the previous page.
+pub async fn list_sites(
+    pool: web::Data<DbPool>,
+    caller: web::ReqData<Caller>,
+    params: web::Query<ListSitesQuery>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::files::dsl::{deployment_id, files, id as files_id};
     use crate::schema::sites::dsl::*;
+    use diesel::dsl::{count, sql};
+    use diesel::sql_types::BigInt;
 
-    let site_id = path_data.into_inner();
-    println!("Site id: {}", site_id);
-    let mut conn = pool.get().expect("couldn't get db connection from pool");
+    let limit = params.limit.unwrap_or(DEFAULT_SITES_LIMIT);
+    if !(1..=MAX_SITES_LIMIT).contains(&limit) {
+        return Err(AppError::Validation(format!(
+            "The limit must be between 1 and {}",
+            MAX_SITES_LIMIT
+        )));
+    }
+    let sort = params.sort.unwrap_or(SiteSort::CreatedAt);
+    let order = params.order.unwrap_or(SortOrder::Desc);
 
-    let site: Site = match sites
-        .filter(id.eq(site_id.clone()))
-        .select(Site::as_select())
-        .first(&mut conn)
-    {
-        Ok(site) => site,
-        Err(err) => {
-            println!("{:?}", err);
-            return HttpResponse::NotFound().finish();
+    let mut conn = pool.get()?;
+
+    // Filters shared by the page and the total
+    let filtered = || {
+        let mut query = sites.into_boxed();
+        if !caller.is_admin {
+            query = query.filter(owner_id.eq(caller.user_id.clone()));
+        }
+        if let Some(host_prefix) = params.host.as_deref().map(str::trim) {
+            query = query.filter(
+                host.like(format!("{}%", escape_like(&host_prefix.to_lowercase())))
+                    .escape('\\'),
+            );
         }
+        if let Some(created_after) = params.created_after {
+            query = query.filter(created_at.gt(created_after.naive_utc()));
+        }
+        if let Some(created_before) = params.created_before {
+            query = query.filter(created_at.lt(created_before.naive_utc()));
+        }
+        query
+    };
+
+    let total: i64 = filtered().count().get_result(&mut conn)?;
+
+    let mut query = filtered();
+    if let Some(cursor) = &params.cursor {
+        // Only sites of the listing can be cursors, so other sites can't be probed
+        let cursor_site: Site = filtered()
+            .filter(id.eq(cursor))
+            .select(Site::as_select())
+            .first(&mut conn)
+            .optional()?
+            .ok_or_else(|| AppError::Validation("Invalid cursor".to_string()))?;
+        let cursor_value = match sort {
+            SiteSort::CreatedAt => cursor_site.created_at,
+            SiteSort::UpdatedAt => cursor_site.updated_at,
+        };
+
+        query = match (sort, order) {
+            (SiteSort::CreatedAt, SortOrder::Asc) => query.filter(
+                created_at
+                    .gt(cursor_value)
+                    .or(created_at.eq(cursor_value).and(id.gt(cursor))),
+            ),
+            (SiteSort::CreatedAt, SortOrder::Desc) => query.filter(
+                created_at
+                    .lt(cursor_value)
+                    .or(created_at.eq(cursor_value).and(id.lt(cursor))),
+            ),
+            (SiteSort::UpdatedAt, SortOrder::Asc) => query.filter(
+                updated_at
+                    .gt(cursor_value)
+                    .or(updated_at.eq(cursor_value).and(id.gt(cursor))),
+            ),
+            (SiteSort::UpdatedAt, SortOrder::Desc) => query.filter(
+                updated_at
+                    .lt(cursor_value)
+                    .or(updated_at.eq(cursor_value).and(id.lt(cursor))),
+            ),
+        };
+    }
+    query = match (sort, order) {
+        (SiteSort::CreatedAt, SortOrder::Asc) => query.order((created_at.asc(), id.asc())),
+        (SiteSort::CreatedAt, SortOrder::Desc) => query.order((created_at.desc(), id.desc())),
+        (SiteSort::UpdatedAt, SortOrder::Asc) => query.order((updated_at.asc(), id.asc())),
+        (SiteSort::UpdatedAt, SortOrder::Desc) => query.order((updated_at.desc(), id.desc())),
+    };
+
+    // One more site than the limit tells whether there is a next page
+    let mut sites_list: Vec<Site> = query
+        .limit(limit + 1)
+        .select(Site::as_select())
+        .load::<Site>(&mut conn)?;
+    let has_more = sites_list.len() as i64 > limit;
+    sites_list.truncate(limit as usize);
+    let next_cursor = has_more
+        .then(|| sites_list.last().map(|site| site.id.clone()))
+        .flatten();
+
+    let page_ids: Vec<String> = sites_list.iter().map(|site| site.id.clone()).collect();
+    let file_stats: HashMap<String, (i64, i64)> = sites
+        .inner_join(files.on(deployment_id.eq(active_deployment_id)))
+        .filter(id.eq_any(page_ids))
+        .group_by(id)
+        .select((
+            id,
+            count(files_id),
+            sql::<BigInt>("COALESCE(SUM(files.size), 0)"),
+        ))
+        .load::<(String, i64, i64)>(&mut conn)?
+        .into_iter()
+        .map(|(stats_site_id, file_count, total_size)| (stats_site_id, (file_count, total_size)))
+        .collect();
+
+    let summaries: Vec<SiteSummary> = sites_list
+        .into_iter()
+        .map(|site| {
+            let (file_count, total_size) = file_stats.get(&site.id).copied().unwrap_or((0, 0));
+            SiteSummary {
+                site,
+                file_count,
+                total_size,
+            }
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "sites": summaries,
+        "total": total,
+        "next_cursor": next_cursor,
+    })))
+}
+
+pub async fn get_site(
+    path_data: web::Path<String>,
+    pool: web::Data<DbPool>,
+    caller: web::ReqData<Caller>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::files::dsl::{deployment_id, files, site_id as file_site_id};
+    use crate::schema::header_rules::dsl::{
+        deployment_id as header_deployment_id, header_rules, position as header_position,
     };
+    use crate::schema::redirect_rules::dsl::{
+        deployment_id as rule_deployment_id, position, redirect_rules,
+    };
+
+    let site_id = path_data.into_inner();
+    let mut conn = pool.get()?;
+
+    let site = find_site(&mut conn, &site_id, &caller)?;
 
     let files_list: Vec<File> = files
         .filter(file_site_id.eq(site_id.clone()))
+        .filter(deployment_id.is(site.active_deployment_id.clone()))
         .select(File::as_select())
-        .load::<File>(&mut conn)
-        .expect("Error loading files");
+        .load::<File>(&mut conn)?;
+
+    let redirects_list: Vec<RedirectRule> = redirect_rules
+        .filter(
+            rule_deployment_id
+                .nullable()
+                .eq(site.active_deployment_id.clone()),
+        )
+        .order(position.asc())
+        .select(RedirectRule::as_select())
+        .load::<RedirectRule>(&mut conn)?;
+
+    let headers_list: Vec<HeaderRule> = header_rules
+        .filter(
+            header_deployment_id
+                .nullable()
+                .eq(site.active_deployment_id.clone()),
+        )
+        .order(header_position.asc())
+        .select(HeaderRule::as_select())
+        .load::<HeaderRule>(&mut conn)?;
 
-    HttpResponse::Ok().json(serde_json::json!({
+    Ok(HttpResponse::Ok().json(serde_json::json!({
         "site": site,
         "files": files_list,
         "total_files": files_list.len(),
-    }))
+        "redirects": redirects_list,
+        "headers": headers_list,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site() -> Site {
+        let now = Utc::now().naive_utc();
+        Site {
+            id: "site".to_string(),
+            host: "blog.nanohost.dev".to_string(),
+            index_file: Some("index.html".to_string()),
+            active_deployment_id: Some("deployment".to_string()),
+            owner_id: None,
+            spa: false,
+            not_found_file: None,
+            title: Some("Blog".to_string()),
+            description: None,
+            tags: "rust".to_string(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn body(value: serde_json::Value) -> PatchSiteBody {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn resolves_changes_of_a_patch() {
+        let file_names = ["index.html", "home.html", "404.html"];
+        let changes = resolve_changes(
+            &site(),
+            &body(json!({"index_file": "home.html", "tags": ["Web", "web", "rust"]})),
+            Some(&file_names),
+        )
+        .unwrap();
+
+        assert_eq!(changes.pages.index_file, "home.html");
+        assert_eq!(changes.pages.not_found_file.as_deref(), Some("404.html"));
+        assert_eq!(changes.title.as_deref(), Some("Blog"));
+        assert_eq!(changes.tags, "web,rust");
+    }
+
+    #[test]
+    fn rejects_an_invalid_patch_before_the_site_is_moved() {
+        // patch_site only moves the site once every other field is resolved
+        let file_names = ["index.html"];
+        for value in [
+            json!({"domain": "moved", "tags": ["bad tag!"]}),
+            json!({"domain": "moved", "title": "a".repeat(MAX_TITLE_LENGTH + 1)}),
+            json!({"domain": "moved", "index_file": "missing.html"}),
+        ] {
+            assert!(
+                matches!(
+                    resolve_changes(&site(), &body(value.clone()), Some(&file_names)),
+                    Err(AppError::Validation(_))
+                ),
+                "{} was accepted",
+                value
+            );
+        }
+    }
 }
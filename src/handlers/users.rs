@@ -0,0 +1,57 @@
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::middleware::auth::{create_api_key, Caller};
+use crate::models::{ApiKeyScope, User};
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize)]
+pub struct CreateUserBody {
+    name: String,
+    #[serde(default)]
+    is_admin: bool,
+}
+
+/// Creates a user along with its first API key. Only admins can create users,
+/// the token is only returned in this response.
+pub async fn create_user(
+    pool: web::Data<DbPool>,
+    caller: web::ReqData<Caller>,
+    body: web::Json<CreateUserBody>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::users::dsl::users;
+
+    if !caller.is_admin {
+        return Err(AppError::Forbidden(
+            "Only admin keys can create users".to_string(),
+        ));
+    }
+
+    let mut conn = pool.get()?;
+
+    let now = Utc::now().naive_utc();
+    let new_user = User {
+        id: ulid::Ulid::new().to_string(),
+        name: body.name.clone(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    let scope = match body.is_admin {
+        true => ApiKeyScope::Admin,
+        false => ApiKeyScope::User,
+    };
+
+    let token = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::insert_into(users).values(&new_user).execute(conn)?;
+        create_api_key(conn, &new_user.id, scope)
+    })?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "user": new_user,
+        "token": token,
+    })))
+}
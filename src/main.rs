@@ -1,47 +1,142 @@
 mod config;
 mod db;
+mod error;
 mod handlers;
+mod middleware;
 mod models;
 mod schema;
 mod services;
 mod utils;
+mod workers;
+
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::db::establish_connection_pool;
-use actix_web::{web, App, HttpServer, Responder};
+use actix_multipart::form::MultipartFormConfig;
+use actix_web::{guard, middleware::from_fn, web, App, HttpServer};
 use aws_config::{BehaviorVersion, Region};
-use handlers::sites;
-use services::{cloudfront_key_value, dynamodb, s3};
+use handlers::{deployments, domains, serve, sites, users};
+use middleware::auth;
+use services::{
+    cloudfront_key_value, dns, dns::TxtResolver, dynamodb, filesystem, s3, storage::Storage,
+};
+use utils::content_type::UploadPolicy;
+use utils::hostname::HostPolicy;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let config = config::Config::new();
     let pool = establish_connection_pool();
 
+    if let Some(admin_api_key) = &config.admin_api_key {
+        auth::ensure_admin_key(&pool, admin_api_key);
+    }
+
     let aws_config = aws_config::defaults(BehaviorVersion::latest())
         .region(Region::new(config.aws_region.clone()))
         .load()
         .await;
 
-    let s3_client = s3::Client::new(&aws_config, &config.aws_s3_bucket_name);
+    let storage_client: Arc<dyn Storage> = match config.storage_backend.as_str() {
+        "local" => Arc::new(filesystem::Client::new(&config.local_storage_path)),
+        "s3" => Arc::new(s3::Client::new(&aws_config, &config.aws_s3_bucket_name)),
+        backend => unreachable!("Unknown storage backend {}", backend),
+    };
     let cloudfront_kvs_client =
         cloudfront_key_value::Client::new(&aws_config, &config.aws_cloudfront_kvs_arn);
     let dynamodb_client = dynamodb::Client::new(&aws_config, &config.aws_dynamodb_table_name);
+    let dns_resolver: Arc<dyn TxtResolver> = Arc::new(dns::Client::new());
+    let upload_policy = UploadPolicy::from_config(&config);
+    let host_policy = HostPolicy::from_config(&config);
+
+    actix_web::rt::spawn(workers::domain_verification::run(
+        pool.clone(),
+        dns_resolver.clone(),
+        dynamodb_client.clone(),
+        Duration::from_secs(config.domain_verification_interval as u64),
+    ));
+    actix_web::rt::spawn(workers::host_redirects::run(
+        pool.clone(),
+        dynamodb_client.clone(),
+        Duration::from_secs(config.host_redirect_cleanup_interval as u64),
+    ));
 
     let port = 8080;
     let address = format!("127.0.0.1:{}", port);
     println!("Server started at http://{}", address);
 
+    let serve_sites = config.serve_sites;
+    let api_host = config.app_url.clone();
+
     HttpServer::new(move || {
+        let api_host = api_host.clone();
+
         App::new()
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(s3_client.clone()))
+            // Leaves room for the text fields sent along with the archive
+            .app_data(
+                MultipartFormConfig::default()
+                    .total_limit(sites::MAX_ARCHIVE_FILE_SIZE + 1024 * 1024),
+            )
+            .app_data(web::Data::from(storage_client.clone()))
             .app_data(web::Data::new(cloudfront_kvs_client.clone()))
             .app_data(web::Data::new(dynamodb_client.clone()))
-            .route("/sites", web::get().to(sites::list_sites))
-            .route("/sites", web::post().to(sites::create_site))
-            .route("/sites/{site_id}", web::get().to(sites::get_site))
-            .route("/sites/{site_id}", web::put().to(sites::update_site))
-            .route("/sites/{site_id}", web::delete().to(sites::delete_site))
+            .app_data(web::Data::from(dns_resolver.clone()))
+            .app_data(web::Data::new(upload_policy.clone()))
+            .app_data(web::Data::new(host_policy.clone()))
+            .configure(|cfg| {
+                // When enabled, requests addressed to any host other than the API
+                // host are served from the matching hosted site.
+                if serve_sites {
+                    cfg.service(
+                        web::scope("")
+                            .guard(guard::fn_guard(move |ctx| {
+                                serve::is_site_request(ctx, &api_host)
+                            }))
+                            .default_service(web::to(serve::serve_site)),
+                    );
+                }
+            })
+            .service(
+                web::scope("")
+                    .wrap(from_fn(auth::require_api_key))
+                    .route("/users", web::post().to(users::create_user))
+                    .route("/sites", web::get().to(sites::list_sites))
+                    .route("/sites", web::post().to(sites::create_site))
+                    .route("/sites/{site_id}", web::get().to(sites::get_site))
+                    .route("/sites/{site_id}", web::put().to(sites::update_site))
+                    .route("/sites/{site_id}", web::patch().to(sites::patch_site))
+                    .route("/sites/{site_id}", web::delete().to(sites::delete_site))
+                    .route(
+                        "/sites/{site_id}/deployments",
+                        web::get().to(deployments::list_deployments),
+                    )
+                    .route(
+                        "/sites/{site_id}/deployments/diff",
+                        web::post().to(deployments::diff_deployment),
+                    )
+                    .route(
+                        "/sites/{site_id}/deployments/{deployment_id}/activate",
+                        web::post().to(deployments::activate),
+                    )
+                    .route(
+                        "/sites/{site_id}/domains",
+                        web::get().to(domains::list_domains),
+                    )
+                    .route(
+                        "/sites/{site_id}/domains",
+                        web::post().to(domains::create_domain),
+                    )
+                    .route(
+                        "/sites/{site_id}/domains/{domain_id}",
+                        web::delete().to(domains::delete_domain),
+                    )
+                    .route(
+                        "/sites/{site_id}/domains/{domain_id}/verify",
+                        web::post().to(domains::verify),
+                    ),
+            )
     })
     .bind(address)?
     .workers(2)
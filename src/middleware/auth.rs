@@ -0,0 +1,150 @@
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::models::{ApiKey, ApiKeyScope, User};
+use crate::utils::hash::sha256_hex;
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web, Error, HttpMessage,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use rand::RngCore;
+
+/// The user owning the API key of the current request,
+/// available to handlers as `web::ReqData<Caller>`.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub user_id: String,
+    pub is_admin: bool,
+}
+
+pub fn hash_token(token: &str) -> String {
+    sha256_hex(token.as_bytes())
+}
+
+/// Generates a new random API token, only its hash is ever stored.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("nh_{}", token)
+}
+
+/// Stores a new API key for the user and returns the plain token.
+pub fn create_api_key(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    scope: ApiKeyScope,
+) -> QueryResult<String> {
+    use crate::schema::api_keys::dsl::api_keys;
+
+    let token = generate_token();
+    let now = Utc::now().naive_utc();
+    let api_key = ApiKey {
+        id: ulid::Ulid::new().to_string(),
+        user_id: user_id.to_string(),
+        token_hash: hash_token(&token),
+        scope: scope.as_str().to_string(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    diesel::insert_into(api_keys)
+        .values(&api_key)
+        .execute(conn)?;
+
+    Ok(token)
+}
+
+/// Makes sure an admin key exists for `token`, so the first users
+/// can be created with the `ADMIN_API_KEY` configured for the service.
+pub fn ensure_admin_key(pool: &DbPool, token: &str) {
+    use crate::schema::api_keys::dsl::{api_keys, token_hash};
+    use crate::schema::users::dsl::users;
+
+    let mut conn = pool.get().expect("couldn't get db connection from pool");
+    let hashed_token = hash_token(token);
+
+    let exists = api_keys
+        .filter(token_hash.eq(hashed_token.clone()))
+        .select(ApiKey::as_select())
+        .first(&mut conn)
+        .optional()
+        .expect("Error loading api keys")
+        .is_some();
+    if exists {
+        return;
+    }
+
+    let now = Utc::now().naive_utc();
+    let admin = User {
+        id: ulid::Ulid::new().to_string(),
+        name: "admin".to_string(),
+        created_at: now,
+        updated_at: now,
+    };
+    let api_key = ApiKey {
+        id: ulid::Ulid::new().to_string(),
+        user_id: admin.id.clone(),
+        token_hash: hashed_token,
+        scope: ApiKeyScope::Admin.as_str().to_string(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::insert_into(users).values(&admin).execute(conn)?;
+        diesel::insert_into(api_keys)
+            .values(&api_key)
+            .execute(conn)?;
+        Ok(())
+    })
+    .expect("Error saving admin api key");
+}
+
+/// Validates the `Authorization: Bearer <token>` header against the stored
+/// API keys and attaches the matching [`Caller`] to the request.
+pub async fn require_api_key(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    use crate::schema::api_keys::dsl::{api_keys, token_hash};
+
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+
+    let token = match token {
+        Some(token) if !token.is_empty() => token,
+        _ => return Err(AppError::Unauthorized.into()),
+    };
+
+    let pool = req
+        .app_data::<web::Data<DbPool>>()
+        .ok_or_else(|| AppError::Internal("Database pool is missing".to_string()))?
+        .clone();
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let api_key: ApiKey = api_keys
+        .filter(token_hash.eq(hash_token(&token)))
+        .select(ApiKey::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(AppError::from)?
+        .ok_or(AppError::Unauthorized)?;
+
+    req.extensions_mut().insert(Caller {
+        user_id: api_key.user_id,
+        is_admin: api_key.scope == ApiKeyScope::Admin.as_str(),
+    });
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_boxed_body)
+}
@@ -1,4 +1,8 @@
-use super::schema::{files, sites};
+use super::schema::{
+    api_keys, deployments, domains, files, header_rules, host_redirects, redirect_rules, sites,
+    users,
+};
+use crate::utils::compression::Encoding;
 use chrono::NaiveDateTime;
 use diesel::{sqlite::Sqlite, Identifiable, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
@@ -10,11 +14,35 @@ pub struct Site {
     pub id: String,
     pub host: String,
     pub index_file: Option<String>,
+    pub active_deployment_id: Option<String>,
+    pub owner_id: Option<String>,
+    /// Unknown paths serve the index file instead of a 404
+    pub spa: bool,
+    /// Served with a 404 for unknown paths of non-SPA sites
+    pub not_found_file: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Stored comma separated, sent as a list
+    #[serde(with = "comma_separated")]
+    pub tags: String,
 
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
+/// (De)serializes a comma separated column as a list of strings.
+mod comma_separated {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(value.split(',').filter(|item| !item.is_empty()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        Ok(Vec::<String>::deserialize(deserializer)?.join(","))
+    }
+}
+
 #[derive(Queryable, Insertable, Identifiable, Deserialize, Selectable, Serialize)]
 #[diesel(belongs_to(Site, foreign_key= site_id))]
 #[diesel(check_for_backend(Sqlite))]
@@ -27,6 +55,222 @@ pub struct File {
     pub mime_type: String,
     pub size: i64,
     pub is_index: bool,
+    pub deployment_id: Option<String>,
+    pub hash: Option<String>,
+    /// Directory this file is the index of, empty for the root index
+    pub index_of: Option<String>,
+    /// Sizes of the precompressed variants, when they were generated
+    pub brotli_size: Option<i64>,
+    pub gzip_size: Option<i64>,
+    /// Cache policy resolved when deploying, `_headers` rules still override it
+    pub cache_control: Option<String>,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl File {
+    /// Encodings of the precompressed variants stored for the file.
+    pub fn encodings(&self) -> Vec<Encoding> {
+        Encoding::ALL
+            .into_iter()
+            .filter(|encoding| match encoding {
+                Encoding::Brotli => self.brotli_size.is_some(),
+                Encoding::Gzip => self.gzip_size.is_some(),
+            })
+            .collect()
+    }
+
+    /// Keys of the object and of its variants.
+    pub fn storage_keys(&self) -> Vec<String> {
+        let mut keys = vec![self.path.clone()];
+        keys.extend(
+            self.encodings()
+                .iter()
+                .map(|encoding| encoding.variant_key(&self.path)),
+        );
+        keys
+    }
+}
+
+pub enum DeploymentStatus {
+    /// Files are being uploaded, the deployment can't be activated yet
+    Pending,
+    /// The deployment currently served for the site
+    Active,
+    /// A previous deployment which can be activated again to roll back
+    Inactive,
+}
+
+impl DeploymentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeploymentStatus::Pending => "pending",
+            DeploymentStatus::Active => "active",
+            DeploymentStatus::Inactive => "inactive",
+        }
+    }
+}
+
+#[derive(Queryable, Insertable, Identifiable, Deserialize, Selectable, Serialize)]
+#[diesel(belongs_to(Site, foreign_key= site_id))]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(table_name = deployments)]
+pub struct Deployment {
+    pub id: String,
+    pub site_id: String,
+    pub status: String,
+    pub file_count: i64,
+    pub total_size: i64,
+    pub index_file: Option<String>,
+    pub spa: bool,
+    pub not_found_file: Option<String>,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Deployment {
+    /// Key prefix under which the files of a deployment are stored.
+    pub fn path_for(site_id: &str, deployment_id: &str) -> String {
+        format!("sites/{}/deployments/{}/", site_id, deployment_id)
+    }
+
+    pub fn path(&self) -> String {
+        Self::path_for(&self.site_id, &self.id)
+    }
+}
+
+#[derive(Queryable, Insertable, Identifiable, Deserialize, Selectable, Serialize)]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(table_name = users)]
+pub struct User {
+    pub id: String,
+    pub name: String,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+pub enum ApiKeyScope {
+    /// Can only manage the sites owned by the key's user
+    User,
+    /// Can manage every site and create users
+    Admin,
+}
+
+impl ApiKeyScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::User => "user",
+            ApiKeyScope::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Queryable, Insertable, Identifiable, Selectable)]
+#[diesel(belongs_to(User, foreign_key= user_id))]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(table_name = api_keys)]
+pub struct ApiKey {
+    pub id: String,
+    pub user_id: String,
+    pub token_hash: String,
+    pub scope: String,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+pub enum DomainStatus {
+    /// Waiting for the `_nanohost` TXT record to be found
+    Pending,
+    /// Ownership was verified, the domain is published to the routing table
+    Verified,
+}
+
+impl DomainStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DomainStatus::Pending => "pending",
+            DomainStatus::Verified => "verified",
+        }
+    }
+}
+
+#[derive(Queryable, Insertable, Identifiable, Deserialize, Selectable, Serialize)]
+#[diesel(belongs_to(Site, foreign_key= site_id))]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(table_name = domains)]
+pub struct Domain {
+    pub id: String,
+    pub site_id: String,
+    pub host: String,
+    pub verification_token: String,
+    pub status: String,
+    pub verified_at: Option<NaiveDateTime>,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Domain {
+    /// Name of the TXT record which must contain the verification token.
+    pub fn verification_record(&self) -> String {
+        format!("_nanohost.{}", self.host)
+    }
+}
+
+#[derive(Queryable, Insertable, Identifiable, Deserialize, Selectable, Serialize)]
+#[diesel(belongs_to(Deployment, foreign_key= deployment_id))]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(table_name = redirect_rules)]
+pub struct RedirectRule {
+    pub id: String,
+    pub site_id: String,
+    pub deployment_id: String,
+    /// Rules are matched in ascending position, the first match wins
+    pub position: i32,
+    pub from_path: String,
+    pub to_path: String,
+    /// 301 and 302 redirect, 200 serves `to_path` without changing the URL
+    pub status: i32,
+    /// Applies the rule even when a file exists at `from_path`
+    pub force: bool,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Insertable, Identifiable, Deserialize, Selectable, Serialize)]
+#[diesel(belongs_to(Deployment, foreign_key= deployment_id))]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(table_name = header_rules)]
+pub struct HeaderRule {
+    pub id: String,
+    pub site_id: String,
+    pub deployment_id: String,
+    /// Later rules override the headers set by earlier ones
+    pub position: i32,
+    pub path: String,
+    pub name: String,
+    pub value: String,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Previous host of a renamed site, redirected to its current host until
+/// `expires_at`.
+#[derive(Queryable, Insertable, Identifiable, Deserialize, Selectable, Serialize)]
+#[diesel(belongs_to(Site, foreign_key= site_id))]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(table_name = host_redirects)]
+pub struct HostRedirect {
+    pub id: String,
+    pub site_id: String,
+    pub host: String,
+    pub expires_at: NaiveDateTime,
 
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
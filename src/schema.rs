@@ -1,5 +1,44 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_keys (id) {
+        id -> Text,
+        user_id -> Text,
+        token_hash -> Text,
+        scope -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    deployments (id) {
+        id -> Text,
+        site_id -> Text,
+        status -> Text,
+        file_count -> BigInt,
+        total_size -> BigInt,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        index_file -> Nullable<Text>,
+        spa -> Bool,
+        not_found_file -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    domains (id) {
+        id -> Text,
+        site_id -> Text,
+        host -> Text,
+        verification_token -> Text,
+        status -> Text,
+        verified_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     files (id) {
         id -> Text,
@@ -11,6 +50,52 @@ diesel::table! {
         is_index -> Bool,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        deployment_id -> Nullable<Text>,
+        hash -> Nullable<Text>,
+        index_of -> Nullable<Text>,
+        brotli_size -> Nullable<BigInt>,
+        gzip_size -> Nullable<BigInt>,
+        cache_control -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    header_rules (id) {
+        id -> Text,
+        site_id -> Text,
+        deployment_id -> Text,
+        position -> Integer,
+        path -> Text,
+        name -> Text,
+        value -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    host_redirects (id) {
+        id -> Text,
+        site_id -> Text,
+        host -> Text,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    redirect_rules (id) {
+        id -> Text,
+        site_id -> Text,
+        deployment_id -> Text,
+        position -> Integer,
+        from_path -> Text,
+        to_path -> Text,
+        status -> Integer,
+        force -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -21,12 +106,45 @@ diesel::table! {
         created_at -> Timestamp,
         updated_at -> Timestamp,
         index_file -> Nullable<Text>,
+        active_deployment_id -> Nullable<Text>,
+        owner_id -> Nullable<Text>,
+        spa -> Bool,
+        not_found_file -> Nullable<Text>,
+        title -> Nullable<Text>,
+        description -> Nullable<Text>,
+        tags -> Text,
+    }
+}
+
+diesel::table! {
+    users (id) {
+        id -> Text,
+        name -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
+diesel::joinable!(api_keys -> users (user_id));
+diesel::joinable!(deployments -> sites (site_id));
+diesel::joinable!(domains -> sites (site_id));
+diesel::joinable!(files -> deployments (deployment_id));
 diesel::joinable!(files -> sites (site_id));
+diesel::joinable!(header_rules -> deployments (deployment_id));
+diesel::joinable!(header_rules -> sites (site_id));
+diesel::joinable!(host_redirects -> sites (site_id));
+diesel::joinable!(redirect_rules -> deployments (deployment_id));
+diesel::joinable!(redirect_rules -> sites (site_id));
+diesel::joinable!(sites -> users (owner_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    deployments,
+    domains,
     files,
+    header_rules,
+    host_redirects,
+    redirect_rules,
     sites,
+    users,
 );
@@ -1,5 +1,11 @@
 use aws_config::SdkConfig as AwsConfig;
-use aws_sdk_cloudfrontkeyvaluestore::Client as CloudFrontClient;
+use aws_sdk_cloudfrontkeyvaluestore::types::{DeleteKeyRequestListItem, PutKeyRequestListItem};
+use aws_sdk_cloudfrontkeyvaluestore::{error::DisplayErrorContext, Client as CloudFrontClient};
+
+use crate::error::AppError;
+
+/// Maximum number of keys changed by a single `UpdateKeys` request.
+const MAX_KEYS_PER_UPDATE: usize = 50;
 
 #[derive(Debug, Clone)]
 pub struct Client {
@@ -15,29 +21,36 @@ impl Client {
         }
     }
 
-    pub async fn get_value(&self, key: &str) -> Result<String, String> {
-        let result = self
-            .cloudfront
-            .get_key()
-            .kvs_arn(&self.kvs_arn)
-            .key(key)
-            .send()
-            .await;
-
-        match result {
-            Ok(response) => {
-                let value = response.value;
-                Ok(value)
-            }
-            Err(e) => {
-                println!("Error getting cloudfront key value: {:?}", e);
-                Err(e.to_string())
-            }
-        }
+    /// The key value store is optional, e.g. when sites are served by nanohost.
+    pub fn is_configured(&self) -> bool {
+        !self.kvs_arn.is_empty()
     }
 
-    pub async fn set_value(&self, key: &str, value: &str) -> Result<(), String> {
-        println!("Setting cloudfront key value: {} => {}", key, value);
+    /// Puts and deletes keys in batches, each batch is applied atomically.
+    pub async fn update_values(
+        &self,
+        puts: Vec<(String, String)>,
+        deletes: Vec<String>,
+    ) -> Result<(), AppError> {
+        let build_error = |e: aws_sdk_cloudfrontkeyvaluestore::error::BuildError| {
+            AppError::Internal(e.to_string())
+        };
+        let puts = puts
+            .into_iter()
+            .map(|(key, value)| {
+                PutKeyRequestListItem::builder()
+                    .key(key)
+                    .value(value)
+                    .build()
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(build_error)?;
+        let deletes = deletes
+            .into_iter()
+            .map(|key| DeleteKeyRequestListItem::builder().key(key).build())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(build_error)?;
+
         let e_tag = self
             .cloudfront
             .describe_key_value_store()
@@ -45,63 +58,48 @@ impl Client {
             .send()
             .await;
 
-        let e_tag = match e_tag {
+        let mut e_tag = match e_tag {
             Ok(response) => response.e_tag,
             Err(e) => {
                 println!("Error getting cloudfront key value: {:#?}", e);
-                return Err(e.to_string());
+                return Err(AppError::EdgeConfig(DisplayErrorContext(&e).to_string()));
             }
         };
 
-        let result = self
-            .cloudfront
-            .put_key()
-            .key(key)
-            .value(value)
-            .kvs_arn(&self.kvs_arn)
-            .if_match(e_tag)
-            .send()
-            .await;
-
-        match result {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                println!("Error setting cloudfront key value: {:#?}", e);
-                Err(e.to_string())
-            }
+        for puts in puts.chunks(MAX_KEYS_PER_UPDATE) {
+            e_tag = self.update_keys(&e_tag, puts.to_vec(), Vec::new()).await?;
+        }
+        for deletes in deletes.chunks(MAX_KEYS_PER_UPDATE) {
+            e_tag = self
+                .update_keys(&e_tag, Vec::new(), deletes.to_vec())
+                .await?;
         }
-    }
-
-    pub async fn delete_value(&self, key: &str) -> Result<(), String> {
-        let e_tag = self
-            .cloudfront
-            .describe_key_value_store()
-            .kvs_arn(&self.kvs_arn)
-            .send()
-            .await;
 
-        let e_tag = match e_tag {
-            Ok(response) => response.e_tag,
-            Err(e) => {
-                println!("Error getting cloudfront key value: {:#?}", e);
-                return Err(e.to_string());
-            }
-        };
+        Ok(())
+    }
 
+    /// Applies a batch of changes and returns the new ETag of the store.
+    async fn update_keys(
+        &self,
+        e_tag: &str,
+        puts: Vec<PutKeyRequestListItem>,
+        deletes: Vec<DeleteKeyRequestListItem>,
+    ) -> Result<String, AppError> {
         let result = self
             .cloudfront
-            .delete_key()
+            .update_keys()
             .kvs_arn(&self.kvs_arn)
-            .key(key)
             .if_match(e_tag)
+            .set_puts(Some(puts))
+            .set_deletes(Some(deletes))
             .send()
             .await;
 
         match result {
-            Ok(_) => Ok(()),
+            Ok(response) => Ok(response.e_tag),
             Err(e) => {
-                println!("Error deleting cloudfront key value: {:#?}", e);
-                Err(e.to_string())
+                println!("Error updating cloudfront key values: {:#?}", e);
+                Err(AppError::EdgeConfig(DisplayErrorContext(&e).to_string()))
             }
         }
     }
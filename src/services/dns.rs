@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+use hickory_resolver::{
+    config::{ResolverConfig, ResolverOpts},
+    error::ResolveErrorKind,
+    TokioAsyncResolver,
+};
+
+use crate::error::AppError;
+
+/// Looks up TXT records, used to verify the ownership of custom domains.
+/// Kept behind a trait so the resolver can be replaced, e.g. by a stub.
+#[async_trait]
+pub trait TxtResolver: Send + Sync {
+    /// Returns the TXT records of `name`, empty if it has none.
+    async fn txt_records(&self, name: &str) -> Result<Vec<String>, AppError>;
+}
+
+/// Resolver using the nameservers of the system configuration,
+/// or public nameservers when the system configuration can't be read.
+#[derive(Clone)]
+pub struct Client {
+    resolver: TokioAsyncResolver,
+}
+
+impl Client {
+    pub fn new() -> Client {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|_| {
+            TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+        });
+
+        Client { resolver }
+    }
+}
+
+#[async_trait]
+impl TxtResolver for Client {
+    async fn txt_records(&self, name: &str) -> Result<Vec<String>, AppError> {
+        let lookup = match self.resolver.txt_lookup(name).await {
+            Ok(lookup) => lookup,
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                return Ok(Vec::new());
+            }
+            Err(e) => {
+                return Err(AppError::Dns(format!(
+                    "Failed to look up TXT records of {}: {}",
+                    name, e
+                )));
+            }
+        };
+
+        Ok(lookup
+            .iter()
+            .map(|txt| {
+                txt.txt_data()
+                    .iter()
+                    .map(|data| String::from_utf8_lossy(data))
+                    .collect::<String>()
+            })
+            .collect())
+    }
+}
@@ -1,7 +1,11 @@
 use std::collections::HashMap;
 
 use aws_config::SdkConfig as AwsConfig;
-use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamodbClient};
+use aws_sdk_dynamodb::{
+    error::DisplayErrorContext, types::AttributeValue, Client as DynamodbClient,
+};
+
+use crate::error::AppError;
 
 #[derive(Debug, Clone)]
 pub struct Client {
@@ -17,18 +21,56 @@ impl Client {
         }
     }
 
-    pub async fn put_item(&self, item: HashMap<String, AttributeValue>) -> Result<(), ()> {
-        let mut input = self.dynamodb.put_item().table_name(self.table_name.clone());
+    /// The routing table is optional, e.g. when sites are served by nanohost,
+    /// writes are then skipped.
+    pub fn is_configured(&self) -> bool {
+        !self.table_name.is_empty()
+    }
+
+    /// Puts an item unless an item with the same `key_attribute` exists with
+    /// another value of `owner_attribute`, so a key can't be claimed by two
+    /// owners even when they race. The owner can overwrite its own item.
+    pub async fn claim_item(
+        &self,
+        item: HashMap<String, AttributeValue>,
+        key_attribute: &str,
+        owner_attribute: &str,
+    ) -> Result<(), AppError> {
+        if !self.is_configured() {
+            return Ok(());
+        }
+
+        let owner = item.get(owner_attribute).cloned().ok_or_else(|| {
+            AppError::Internal(format!("Item has no {} attribute", owner_attribute))
+        })?;
+
+        let mut input = self
+            .dynamodb
+            .put_item()
+            .table_name(self.table_name.clone())
+            .condition_expression("attribute_not_exists(#key) OR #owner = :owner")
+            .expression_attribute_names("#key", key_attribute)
+            .expression_attribute_names("#owner", owner_attribute)
+            .expression_attribute_values(":owner", owner);
         for (key, value) in item {
             input = input.item(key, value);
         }
 
-        let _response = input.send().await.expect("Failed to put item");
+        input.send().await.map_err(|e| match e.as_service_error() {
+            Some(service_error) if service_error.is_conditional_check_failed_exception() => {
+                AppError::Conflict(format!("{} is already claimed", key_attribute))
+            }
+            _ => AppError::Routing(format!("Failed to put item: {}", DisplayErrorContext(&e))),
+        })?;
 
         Ok(())
     }
 
-    pub async fn delete_item(&self, key: HashMap<String, AttributeValue>) -> Result<(), ()> {
+    pub async fn delete_item(&self, key: HashMap<String, AttributeValue>) -> Result<(), AppError> {
+        if !self.is_configured() {
+            return Ok(());
+        }
+
         let mut input = self
             .dynamodb
             .delete_item()
@@ -37,8 +79,51 @@ impl Client {
             input = input.key(key, value);
         }
 
-        let _response = input.send().await.expect("Failed to delete item");
+        input.send().await.map_err(|e| {
+            AppError::Routing(format!(
+                "Failed to delete item: {}",
+                DisplayErrorContext(&e)
+            ))
+        })?;
 
         Ok(())
     }
+
+    /// Deletes an item only while its `owner_attribute` is `owner`, an item
+    /// claimed by another owner since (or already deleted) is left alone.
+    pub async fn release_item(
+        &self,
+        key: HashMap<String, AttributeValue>,
+        owner_attribute: &str,
+        owner: &str,
+    ) -> Result<(), AppError> {
+        if !self.is_configured() {
+            return Ok(());
+        }
+
+        let mut input = self
+            .dynamodb
+            .delete_item()
+            .table_name(self.table_name.clone())
+            .condition_expression("#owner = :owner")
+            .expression_attribute_names("#owner", owner_attribute)
+            .expression_attribute_values(":owner", AttributeValue::S(owner.to_string()));
+        for (key, value) in key {
+            input = input.key(key, value);
+        }
+
+        match input.send().await {
+            Ok(_) => Ok(()),
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(AppError::Routing(format!(
+                "Failed to delete item: {}",
+                DisplayErrorContext(&e)
+            ))),
+        }
+    }
 }
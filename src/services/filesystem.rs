@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use actix_multipart::form::tempfile::TempFile;
+use async_trait::async_trait;
+use aws_sdk_s3::primitives::ByteStream;
+use tokio::fs;
+
+use crate::error::AppError;
+use crate::services::storage::{ObjectHeaders, Storage};
+use crate::utils::hash::sha256_file_async;
+use crate::utils::upload_file::UploadedFile;
+
+/// Local disk storage, useful for development and offline CI.
+/// Objects are stored as regular files at `{root}/{key}`, object headers
+/// aren't stored since nanohost applies them when serving the files.
+#[derive(Debug, Clone)]
+pub struct Client {
+    root: PathBuf,
+}
+
+impl Client {
+    pub fn new(root: impl AsRef<Path>) -> Client {
+        Client {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Path of the object stored under `key`. Keys are relative paths made of
+    /// plain names only, so no key can point outside of the root.
+    fn path(&self, key: &str) -> Result<PathBuf, AppError> {
+        let is_contained = !key.is_empty()
+            && !key.starts_with(['/', '\\'])
+            && Path::new(key)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+        if !is_contained {
+            return Err(AppError::Storage(format!("Invalid key {}", key)));
+        }
+
+        Ok(self.root.join(key))
+    }
+
+    /// Removes the directories left empty by a deleted object, up to the root,
+    /// so a discarded deployment doesn't leave its directory tree behind.
+    async fn remove_empty_parents(&self, path: &Path) {
+        let mut directory = path.parent();
+        while let Some(current) = directory {
+            if current == self.root || fs::remove_dir(current).await.is_err() {
+                break;
+            }
+            directory = current.parent();
+        }
+    }
+
+    async fn upload_and_remove(
+        &self,
+        file: TempFile,
+        key_prefix: &str,
+    ) -> Result<UploadedFile, AppError> {
+        let filename = file
+            .file_name
+            .as_deref()
+            .ok_or_else(|| AppError::Validation("File name is missing".to_string()))?;
+        let key = format!("{key_prefix}{filename}");
+        let file_content_type = file
+            .content_type
+            .clone()
+            .ok_or_else(|| AppError::Validation("File content type is missing".to_string()))?
+            .to_string();
+
+        let hash = sha256_file_async(file.file.path()).await?;
+
+        let destination = self.path(&key)?;
+        let storage_error =
+            |e: std::io::Error| AppError::Storage(format!("Failed to store {}: {}", key, e));
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).await.map_err(storage_error)?;
+        }
+        fs::copy(file.file.path(), &destination)
+            .await
+            .map_err(storage_error)?;
+        fs::remove_file(file.file.path()).await?;
+
+        Ok(UploadedFile::new(
+            filename,
+            file_content_type,
+            file.size as i64,
+            hash,
+            &key,
+            self.url(&key),
+        ))
+    }
+}
+
+#[async_trait]
+impl Storage for Client {
+    fn url(&self, key: &str) -> String {
+        format!("file://{}", self.root.join(key).display())
+    }
+
+    async fn fetch_file(&self, key: &str) -> Result<(u64, ByteStream), AppError> {
+        let path = self.path(key)?;
+        let size = match fs::metadata(&path).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(AppError::NotFound("File not found".to_string()));
+            }
+            Err(e) => {
+                return Err(AppError::Storage(format!("Failed to read {}: {}", key, e)));
+            }
+        };
+        let body = ByteStream::from_path(&path)
+            .await
+            .map_err(|e| AppError::Storage(format!("Failed to read {}: {}", key, e)))?;
+
+        Ok((size, body))
+    }
+
+    async fn upload_files(
+        &self,
+        temp_files: Vec<TempFile>,
+        key_prefix: &str,
+        _headers: &HashMap<String, ObjectHeaders>,
+    ) -> Result<Vec<UploadedFile>, AppError> {
+        let mut uploaded_files = Vec::with_capacity(temp_files.len());
+        for file in temp_files {
+            uploaded_files.push(self.upload_and_remove(file, key_prefix).await?);
+        }
+
+        Ok(uploaded_files)
+    }
+
+    async fn copy_files(
+        &self,
+        keys: Vec<(String, String)>,
+        _headers: &HashMap<String, ObjectHeaders>,
+    ) -> Result<(), AppError> {
+        for (from, to) in keys {
+            let source = self.path(&from)?;
+            let destination = self.path(&to)?;
+            let storage_error =
+                |e: std::io::Error| AppError::Storage(format!("Failed to copy {}: {}", from, e));
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent).await.map_err(storage_error)?;
+            }
+            fs::copy(source, &destination)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        Ok(())
+    }
+
+    async fn delete_files(&self, keys: Vec<String>) -> Result<(), AppError> {
+        for key in keys {
+            let path = self.path(&key)?;
+            match fs::remove_file(&path).await {
+                Ok(_) => self.remove_empty_parents(&path).await,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => {
+                    return Err(AppError::Storage(format!(
+                        "Failed to delete {}: {}",
+                        key, e
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn delete_files_removes_empty_directories() {
+        let root = std::env::temp_dir().join(format!("nanohost-fs-{}", std::process::id()));
+        let client = Client::new(&root);
+        for key in [
+            "sites/a/deployments/b/index.html",
+            "sites/a/deployments/c/index.html",
+        ] {
+            let path = client.path(key).unwrap();
+            fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+            fs::write(&path, "hello").await.unwrap();
+        }
+
+        client
+            .delete_files(vec!["sites/a/deployments/b/index.html".to_string()])
+            .await
+            .unwrap();
+        assert!(!root.join("sites/a/deployments/b").exists());
+        assert!(root.join("sites/a/deployments/c").exists());
+
+        client
+            .delete_files(vec!["sites/a/deployments/c/index.html".to_string()])
+            .await
+            .unwrap();
+        assert!(!root.join("sites").exists());
+        assert!(root.exists());
+
+        fs::remove_dir(&root).await.unwrap();
+    }
+
+    #[test]
+    fn path_accepts_relative_keys() {
+        let client = Client::new("/data");
+        assert_eq!(
+            client.path("sites/a/deployments/b/index.html").unwrap(),
+            PathBuf::from("/data/sites/a/deployments/b/index.html")
+        );
+    }
+
+    #[test]
+    fn path_rejects_keys_escaping_the_root() {
+        let client = Client::new("/data");
+        for key in [
+            "",
+            "/etc/passwd",
+            "../etc/passwd",
+            "sites/../../etc/passwd",
+            "\\etc\\passwd",
+        ] {
+            assert!(
+                matches!(client.path(key), Err(AppError::Storage(_))),
+                "{} was accepted",
+                key
+            );
+        }
+    }
+}
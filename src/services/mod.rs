@@ -1,3 +1,6 @@
 pub mod cloudfront_key_value;
+pub mod dns;
 pub mod dynamodb;
+pub mod filesystem;
 pub mod s3;
+pub mod storage;
@@ -1,14 +1,43 @@
+use std::collections::HashMap;
 use std::env;
 
 use actix_multipart::form::tempfile::TempFile;
+use async_trait::async_trait;
 use aws_config::SdkConfig as AwsConfig;
-use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use aws_sdk_s3::error::DisplayErrorContext;
+use aws_sdk_s3::types::{
+    CompletedMultipartUpload, CompletedPart, Delete, MetadataDirective, ObjectIdentifier,
+};
 use aws_sdk_s3::{primitives::ByteStream, Client as S3Client};
-use futures_util::{stream, StreamExt as _};
-use tokio::{fs, io::AsyncReadExt as _};
+use aws_smithy_types::byte_stream::Length;
+use futures_util::{stream, StreamExt as _, TryStreamExt as _};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use tokio::fs;
 
+use crate::error::AppError;
+use crate::services::storage::{ObjectHeaders, Storage};
+use crate::utils::compression::{compress, is_compressible, Encoding};
+use crate::utils::hash::sha256_file_async;
 use crate::utils::upload_file::UploadedFile;
 
+/// Characters escaped in the `x-amz-copy-source` header, `/` separates the
+/// bucket and the key segments so it's kept as is.
+const COPY_SOURCE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Maximum number of keys deleted by a single `DeleteObjects` request.
+const MAX_KEYS_PER_DELETE: usize = 1000;
+
+/// Files larger than this are uploaded in parts.
+const MULTIPART_THRESHOLD: u64 = 16 * 1024 * 1024; // 16MB
+
+/// Size of the parts of a multipart upload, S3 requires at least 5MB.
+const PART_SIZE: u64 = 8 * 1024 * 1024; // 8MB
+
 #[derive(Debug, Clone)]
 pub struct Client {
     s3: S3Client,
@@ -23,127 +52,430 @@ impl Client {
         }
     }
 
-    pub fn url(&self, key: &str) -> String {
-        format!(
-            "https://{}.s3.{}.amazonaws.com/{key}",
-            env::var("AWS_S3_BUCKET_NAME").unwrap(),
-            env::var("AWS_REGION").unwrap(),
-        )
-    }
-
-    pub async fn fetch_file(&self, key: &str) -> Option<(u64, ByteStream)> {
-        let object = self
-            .s3
-            .get_object()
-            .bucket(&self.bucket_name)
-            .key(key)
-            .send()
-            .await
-            .ok()?;
-
-        Some((
-            object
-                .content_length()
-                .and_then(|len| len.try_into().ok())
-                .expect("file has invalid size"),
-            object.body,
-        ))
-    }
-
-    pub async fn upload_files(
+    async fn upload_and_remove(
         &self,
-        temp_files: Vec<TempFile>,
+        file: TempFile,
         key_prefix: &str,
-    ) -> actix_web::Result<Vec<UploadedFile>> {
-        let uploaded_files = stream::iter(temp_files)
-            .map(|file| self.upload_and_remove(file, key_prefix))
-            // upload files concurrently, up to 2 at a time
-            .buffer_unordered(2)
-            .collect()
-            .await;
-
-        Ok(uploaded_files)
+        headers: &HashMap<String, ObjectHeaders>,
+    ) -> Result<UploadedFile, AppError> {
+        let uploaded_file = self.upload(&file, key_prefix, headers).await?;
+        tokio::fs::remove_file(file.file.path()).await?;
+        Ok(uploaded_file)
     }
 
-    async fn upload_and_remove(&self, file: TempFile, key_prefix: &str) -> UploadedFile {
-        let uploaded_file = self.upload(&file, key_prefix).await;
-        tokio::fs::remove_file(file.file.path()).await.unwrap();
-        uploaded_file
-    }
-
-    async fn upload(&self, file: &TempFile, key_prefix: &str) -> UploadedFile {
-        let filename = file.file_name.as_deref().expect("file name is missing");
+    async fn upload(
+        &self,
+        file: &TempFile,
+        key_prefix: &str,
+        headers: &HashMap<String, ObjectHeaders>,
+    ) -> Result<UploadedFile, AppError> {
+        let filename = file
+            .file_name
+            .as_deref()
+            .ok_or_else(|| AppError::Validation("File name is missing".to_string()))?;
         let key = format!("{key_prefix}{filename}");
         let file_content_type = file
             .content_type
             .clone()
-            .expect("file content type is missing")
+            .ok_or_else(|| AppError::Validation("File content type is missing".to_string()))?
             .to_string();
+        let object_headers = match headers.get(&key) {
+            Some(object_headers) => object_headers.clone(),
+            None => ObjectHeaders {
+                content_type: file_content_type.clone(),
+                cache_control: None,
+                content_encoding: None,
+            },
+        };
         let file_size = file.size;
 
+        let hash = sha256_file_async(file.file.path()).await?;
         let s3_url = self
-            .put_object_from_file(file.file.path().to_str().unwrap(), &key, &file_content_type)
-            .await;
-        UploadedFile::new(filename, file_content_type, file_size as i64, key, s3_url)
+            .put_object_from_file(file.file.path(), &key, &object_headers)
+            .await?;
+        let mut uploaded_file = UploadedFile::new(
+            filename,
+            &file_content_type,
+            file_size as i64,
+            hash,
+            &key,
+            s3_url,
+        );
+
+        if is_compressible(&file_content_type, file_size as u64) {
+            for encoding in Encoding::ALL {
+                let variant_size = self
+                    .upload_variant(file.file.path(), &key, &object_headers, encoding)
+                    .await?;
+                match encoding {
+                    Encoding::Brotli => uploaded_file.brotli_size = variant_size,
+                    Encoding::Gzip => uploaded_file.gzip_size = variant_size,
+                }
+            }
+        }
+
+        Ok(uploaded_file)
+    }
+
+    /// Compresses a file and uploads it next to the original with its
+    /// `Content-Encoding`. Variants which don't save at least a tenth of
+    /// the size aren't kept, and `None` is returned.
+    async fn upload_variant(
+        &self,
+        local_path: &std::path::Path,
+        key: &str,
+        headers: &ObjectHeaders,
+        encoding: Encoding,
+    ) -> Result<Option<i64>, AppError> {
+        let path = local_path.to_path_buf();
+        let compressed = tokio::task::spawn_blocking(move || compress(&path, encoding))
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))??;
+
+        let size = fs::metadata(local_path).await?.len();
+        let compressed_size = compressed.as_file().metadata()?.len();
+        if compressed_size > size * 9 / 10 {
+            return Ok(None);
+        }
+
+        let variant_headers = ObjectHeaders {
+            content_encoding: Some(encoding.as_str().to_string()),
+            ..headers.clone()
+        };
+        self.put_object_from_file(
+            compressed.path(),
+            &encoding.variant_key(key),
+            &variant_headers,
+        )
+        .await?;
+
+        Ok(Some(compressed_size as i64))
     }
 
+    /// Streams a file to S3 and returns its URL. The file is read from disk
+    /// while being sent, so it never has to fit in memory.
     async fn put_object_from_file(
         &self,
-        local_path: &str,
+        local_path: &std::path::Path,
         key: &str,
-        content_type: &str,
-    ) -> String {
-        let mut file = fs::File::open(local_path).await.unwrap();
+        headers: &ObjectHeaders,
+    ) -> Result<String, AppError> {
+        let size = fs::metadata(local_path).await?.len();
 
-        let size_estimate = file
-            .metadata()
-            .await
-            .map(|md| md.len())
-            .unwrap_or(1024)
-            .try_into()
-            .expect("file too big");
+        if size > MULTIPART_THRESHOLD {
+            self.put_object_multipart(local_path, key, headers, size)
+                .await?;
+            return Ok(self.url(key));
+        }
 
-        let mut contents = Vec::with_capacity(size_estimate);
-        file.read_to_end(&mut contents).await.unwrap();
+        let body = ByteStream::from_path(local_path).await.map_err(|e| {
+            AppError::Storage(format!("Failed to read {}: {}", local_path.display(), e))
+        })?;
 
-        let _res = self
-            .s3
+        self.s3
             .put_object()
             .bucket(&self.bucket_name)
             .key(key)
-            .content_type(content_type)
-            .body(ByteStream::from(contents))
+            .content_type(&headers.content_type)
+            .set_cache_control(headers.cache_control.clone())
+            .set_content_encoding(headers.content_encoding.clone())
+            .body(body)
             .send()
             .await
-            .expect("Failed to put object");
+            .map_err(|e| {
+                AppError::Storage(format!(
+                    "Failed to put object {}: {}",
+                    key,
+                    DisplayErrorContext(&e)
+                ))
+            })?;
 
-        self.url(key)
+        Ok(self.url(key))
     }
 
-    pub async fn delete_files(&self, keys: Vec<String>) -> bool {
-        let keys = keys
-            .iter()
-            .map(|key| ObjectIdentifier::builder().key(key).build())
-            .collect::<Result<Vec<_>, _>>()
-            .expect("Failed to build object identifiers");
+    /// Uploads a large file in parts, the upload is aborted if a part fails
+    /// so S3 doesn't keep the parts already uploaded.
+    async fn put_object_multipart(
+        &self,
+        local_path: &std::path::Path,
+        key: &str,
+        headers: &ObjectHeaders,
+        size: u64,
+    ) -> Result<(), AppError> {
+        let upload = self
+            .s3
+            .create_multipart_upload()
+            .bucket(&self.bucket_name)
+            .key(key)
+            .content_type(&headers.content_type)
+            .set_cache_control(headers.cache_control.clone())
+            .set_content_encoding(headers.content_encoding.clone())
+            .send()
+            .await
+            .map_err(|e| {
+                AppError::Storage(format!(
+                    "Failed to start the upload of {}: {}",
+                    key,
+                    DisplayErrorContext(&e)
+                ))
+            })?;
+        let upload_id = upload
+            .upload_id()
+            .ok_or_else(|| AppError::Storage(format!("Upload of {} has no id", key)))?;
+
+        let parts = match self.upload_parts(local_path, key, upload_id, size).await {
+            Ok(parts) => parts,
+            Err(e) => {
+                if let Err(abort_error) = self
+                    .s3
+                    .abort_multipart_upload()
+                    .bucket(&self.bucket_name)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .send()
+                    .await
+                {
+                    println!(
+                        "Error aborting the upload of {}: {}",
+                        key,
+                        DisplayErrorContext(&abort_error)
+                    );
+                }
+                return Err(e);
+            }
+        };
 
         self.s3
-            .delete_objects()
+            .complete_multipart_upload()
             .bucket(&self.bucket_name)
-            .delete(Delete::builder().set_objects(Some(keys)).build().unwrap())
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
             .send()
             .await
-            .is_ok()
+            .map_err(|e| {
+                AppError::Storage(format!(
+                    "Failed to complete the upload of {}: {}",
+                    key,
+                    DisplayErrorContext(&e)
+                ))
+            })?;
+
+        Ok(())
     }
 
-    /// Attempts to deletes object from S3. Returns true if successful.
-    pub async fn delete_file(&self, key: &str) -> bool {
-        self.s3
-            .delete_object()
+    /// Uploads the parts one after the other, each part is streamed from its
+    /// offset in the file.
+    async fn upload_parts(
+        &self,
+        local_path: &std::path::Path,
+        key: &str,
+        upload_id: &str,
+        size: u64,
+    ) -> Result<Vec<CompletedPart>, AppError> {
+        let part_count = size.div_ceil(PART_SIZE);
+        let mut parts = Vec::with_capacity(part_count as usize);
+
+        for index in 0..part_count {
+            let offset = index * PART_SIZE;
+            let body = ByteStream::read_from()
+                .path(local_path)
+                .offset(offset)
+                .length(Length::Exact(PART_SIZE.min(size - offset)))
+                .build()
+                .await
+                .map_err(|e| {
+                    AppError::Storage(format!("Failed to read {}: {}", local_path.display(), e))
+                })?;
+
+            // Part numbers start at 1
+            let part_number = index as i32 + 1;
+            let part = self
+                .s3
+                .upload_part()
+                .bucket(&self.bucket_name)
+                .key(key)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .body(body)
+                .send()
+                .await
+                .map_err(|e| {
+                    AppError::Storage(format!(
+                        "Failed to upload part {} of {}: {}",
+                        part_number,
+                        key,
+                        DisplayErrorContext(&e)
+                    ))
+                })?;
+
+            parts.push(
+                CompletedPart::builder()
+                    .set_e_tag(part.e_tag().map(str::to_string))
+                    .part_number(part_number)
+                    .build(),
+            );
+        }
+
+        Ok(parts)
+    }
+
+    async fn copy_object(
+        &self,
+        from: &str,
+        to: &str,
+        headers: Option<&ObjectHeaders>,
+    ) -> Result<(), AppError> {
+        let copy_source = format!("{}/{}", self.bucket_name, from);
+
+        let mut request = self
+            .s3
+            .copy_object()
+            .bucket(&self.bucket_name)
+            .copy_source(utf8_percent_encode(&copy_source, COPY_SOURCE).to_string())
+            .key(to);
+        if let Some(headers) = headers {
+            request = request
+                .metadata_directive(MetadataDirective::Replace)
+                .content_type(&headers.content_type)
+                .set_cache_control(headers.cache_control.clone())
+                .set_content_encoding(headers.content_encoding.clone());
+        }
+
+        request.send().await.map_err(|e| {
+            AppError::Storage(format!(
+                "Failed to copy object {}: {}",
+                from,
+                DisplayErrorContext(&e)
+            ))
+        })?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for Client {
+    fn url(&self, key: &str) -> String {
+        format!(
+            "https://{}.s3.{}.amazonaws.com/{key}",
+            env::var("AWS_S3_BUCKET_NAME").unwrap_or_default(),
+            env::var("AWS_REGION").unwrap_or_default(),
+        )
+    }
+
+    async fn fetch_file(&self, key: &str) -> Result<(u64, ByteStream), AppError> {
+        let object = self
+            .s3
+            .get_object()
             .bucket(&self.bucket_name)
             .key(key)
             .send()
             .await
-            .is_ok()
+            .map_err(|e| match e.as_service_error() {
+                Some(service_error) if service_error.is_no_such_key() => {
+                    AppError::NotFound("File not found".to_string())
+                }
+                _ => AppError::Storage(format!(
+                    "Failed to get object {}: {}",
+                    key,
+                    DisplayErrorContext(&e)
+                )),
+            })?;
+
+        let size = object
+            .content_length()
+            .and_then(|len| len.try_into().ok())
+            .ok_or_else(|| AppError::Storage(format!("Object {} has an invalid size", key)))?;
+
+        Ok((size, object.body))
+    }
+
+    async fn upload_files(
+        &self,
+        temp_files: Vec<TempFile>,
+        key_prefix: &str,
+        headers: &HashMap<String, ObjectHeaders>,
+    ) -> Result<Vec<UploadedFile>, AppError> {
+        let uploaded_files: Vec<Result<UploadedFile, AppError>> = stream::iter(temp_files)
+            .map(|file| self.upload_and_remove(file, key_prefix, headers))
+            // upload files concurrently, up to 2 at a time
+            .buffer_unordered(2)
+            .collect()
+            .await;
+
+        uploaded_files.into_iter().collect()
+    }
+
+    async fn copy_files(
+        &self,
+        keys: Vec<(String, String)>,
+        headers: &HashMap<String, ObjectHeaders>,
+    ) -> Result<(), AppError> {
+        stream::iter(keys)
+            .map(|(from, to)| async move { self.copy_object(&from, &to, headers.get(&to)).await })
+            // copies happen server side, so more of them can run at once
+            .buffer_unordered(8)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_files(&self, keys: Vec<String>) -> Result<(), AppError> {
+        // Every batch is attempted, the keys which couldn't be deleted
+        // are reported together
+        let mut failed_keys: Vec<String> = Vec::new();
+        for batch in keys.chunks(MAX_KEYS_PER_DELETE) {
+            let objects = batch
+                .iter()
+                .map(|key| ObjectIdentifier::builder().key(key).build())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+
+            // Quiet mode only lists the keys which failed
+            let delete = Delete::builder()
+                .set_objects(Some(objects))
+                .quiet(true)
+                .build()
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+
+            let output = self
+                .s3
+                .delete_objects()
+                .bucket(&self.bucket_name)
+                .delete(delete)
+                .send()
+                .await
+                .map_err(|e| {
+                    AppError::Storage(format!(
+                        "Failed to delete objects: {}",
+                        DisplayErrorContext(&e)
+                    ))
+                })?;
+
+            for error in output.errors() {
+                let key = error.key().unwrap_or("unknown key");
+                println!(
+                    "Error deleting {}: {}",
+                    key,
+                    error.message().unwrap_or_default()
+                );
+                failed_keys.push(key.to_string());
+            }
+        }
+
+        if !failed_keys.is_empty() {
+            return Err(AppError::Storage(format!(
+                "Failed to delete {}",
+                failed_keys.join(", ")
+            )));
+        }
+
+        Ok(())
     }
 }
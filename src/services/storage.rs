@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+use actix_multipart::form::tempfile::TempFile;
+use async_trait::async_trait;
+use aws_sdk_s3::primitives::ByteStream;
+
+use crate::error::AppError;
+use crate::utils::upload_file::UploadedFile;
+
+/// Headers stored with an object, sent back when it is served straight
+/// from the storage (e.g. by CloudFront in front of S3).
+#[derive(Debug, Clone)]
+pub struct ObjectHeaders {
+    pub content_type: String,
+    pub cache_control: Option<String>,
+    /// Set on the precompressed variants of a file
+    pub content_encoding: Option<String>,
+}
+
+/// Object storage used to keep the files of every hosted site.
+///
+/// Keys are always relative (e.g. `sites/{id}/index.html`) so the same
+/// key layout works for every backend.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Public URL of the object stored under `key`.
+    fn url(&self, key: &str) -> String;
+
+    /// Returns the size and content of the object, `AppError::NotFound` if it doesn't exist.
+    async fn fetch_file(&self, key: &str) -> Result<(u64, ByteStream), AppError>;
+
+    /// Uploads every file under `key_prefix` and removes the local temp files.
+    /// `headers` are keyed by object key, other objects get the content type
+    /// of their temp file, which is the one returned in any case.
+    async fn upload_files(
+        &self,
+        temp_files: Vec<TempFile>,
+        key_prefix: &str,
+        headers: &HashMap<String, ObjectHeaders>,
+    ) -> Result<Vec<UploadedFile>, AppError>;
+
+    /// Copies objects within the storage, each pair is `(from, to)`. Copies
+    /// listed in `headers` (keyed by `to`) get these headers instead of the
+    /// ones of the original object.
+    async fn copy_files(
+        &self,
+        keys: Vec<(String, String)>,
+        headers: &HashMap<String, ObjectHeaders>,
+    ) -> Result<(), AppError>;
+
+    /// Deletes all objects, missing objects are ignored.
+    async fn delete_files(&self, keys: Vec<String>) -> Result<(), AppError>;
+}
@@ -0,0 +1,251 @@
+use actix_multipart::form::tempfile::TempFile;
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use tempfile::NamedTempFile;
+
+use crate::error::AppError;
+use crate::utils::{content_type, tar, zip};
+
+/// Maximum number of entries in an archive.
+pub const MAX_ENTRIES: usize = 10_000;
+
+/// Maximum size of all the extracted files.
+pub const MAX_TOTAL_SIZE: u64 = 1024 * 1024 * 1024; // 1GB
+
+/// Archives compressed more than this are rejected as likely archive bombs,
+/// small files are exempt since they legitimately compress well.
+pub const MAX_COMPRESSION_RATIO: u64 = 100;
+pub const MIN_RATIO_CHECKED_SIZE: u64 = 1024 * 1024; // 1MB
+
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGzip,
+    TarZstd,
+}
+
+impl ArchiveFormat {
+    /// Detects the format from the magic bytes at the start of the file,
+    /// the content type sent by the client isn't trusted.
+    pub fn detect(file: &mut File) -> io::Result<Option<ArchiveFormat>> {
+        let mut header = Vec::new();
+        file.take(262).read_to_end(&mut header)?;
+        file.rewind()?;
+
+        let format = if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            Some(ArchiveFormat::Zip)
+        } else if header.starts_with(&[0x1f, 0x8b]) {
+            Some(ArchiveFormat::TarGzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(ArchiveFormat::TarZstd)
+        } else if header.get(257..262) == Some(b"ustar") {
+            Some(ArchiveFormat::Tar)
+        } else {
+            None
+        };
+
+        Ok(format)
+    }
+}
+
+/// Extracts a zip or (optionally compressed) tar archive.
+pub fn extract_archive(mut file: File) -> Result<Vec<TempFile>, AppError> {
+    let archive_size = file.metadata()?.len();
+
+    match ArchiveFormat::detect(&mut file)? {
+        Some(ArchiveFormat::Zip) => zip::extract_file(file),
+        Some(ArchiveFormat::Tar) => tar::extract_tar(file, archive_size),
+        Some(ArchiveFormat::TarGzip) => {
+            tar::extract_tar(flate2::read::GzDecoder::new(file), archive_size)
+        }
+        Some(ArchiveFormat::TarZstd) => {
+            let decoder = zstd::Decoder::new(file)
+                .map_err(|e| AppError::InvalidArchive(format!("Invalid zstd archive: {}", e)))?;
+            tar::extract_tar(decoder, archive_size)
+        }
+        None => Err(AppError::Validation(
+            "Invalid file type. Only zip, tar, tar.gz and tar.zst archives are allowed".to_string(),
+        )),
+    }
+}
+
+/// Skips macOS metadata and .DS_Store files
+/// .DS_Store files are created by macOS Finder and are not useful for our purposes.
+/// Expects a path returned by `normalize_path`, so `./` prefixes are gone.
+pub fn is_ignored(name: &str) -> bool {
+    name.starts_with("__MACOSX") || name.ends_with(".DS_Store")
+}
+
+/// Normalizes a file path to a relative `/` separated path, rejecting
+/// paths which could escape the site prefix once used as a storage key.
+pub fn normalize_path(path: &str) -> Result<String, AppError> {
+    let invalid_path = |reason: &str| AppError::Validation(format!("{}: {}", reason, path));
+
+    if path.chars().any(|c| c.is_control()) {
+        return Err(invalid_path("Path contains control characters"));
+    }
+
+    let path = path.replace('\\', "/");
+    if path.starts_with('/') || path.split('/').next().is_some_and(|c| c.ends_with(':')) {
+        return Err(invalid_path("Absolute paths are not allowed"));
+    }
+
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => return Err(invalid_path("Parent directory references are not allowed")),
+            component => components.push(component),
+        }
+    }
+
+    if components.is_empty() {
+        return Err(invalid_path("Path is empty"));
+    }
+
+    Ok(components.join("/"))
+}
+
+/// Removes the top-level directory shared by every file, e.g. the `dist/`
+/// of a zipped build folder, and returns the prefix which was stripped.
+pub fn strip_common_root(files: &mut [TempFile]) -> Option<String> {
+    let root_of = |file: &TempFile| {
+        file.file_name
+            .as_deref()
+            .and_then(|name| name.split_once('/'))
+            .map(|(root, _)| root.to_string())
+    };
+
+    let root = root_of(files.first()?)?;
+    if !files
+        .iter()
+        .all(|file| root_of(file).as_ref() == Some(&root))
+    {
+        return None;
+    }
+
+    let prefix = format!("{}/", root);
+    for file in files.iter_mut() {
+        file.file_name = file
+            .file_name
+            .as_deref()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .map(str::to_string);
+    }
+
+    Some(prefix)
+}
+
+/// Files extracted so far, enforcing the limits shared by every format.
+#[derive(Default)]
+pub struct Extraction {
+    files: Vec<TempFile>,
+    total_size: u64,
+}
+
+impl Extraction {
+    pub fn total_size(&self) -> u64 {
+        self.total_size
+    }
+
+    /// Streams an entry to a temp file. The sizes in archive headers can't be
+    /// trusted, so the entry is read with the remaining budget and rejected
+    /// if it exceeds it.
+    pub fn add(&mut self, entry: impl Read, file_name: String) -> Result<(), AppError> {
+        if self.files.len() >= MAX_ENTRIES {
+            return Err(AppError::InvalidArchive(format!(
+                "Archive has too many entries. Maximum is {}",
+                MAX_ENTRIES
+            )));
+        }
+
+        let remaining_size = MAX_TOTAL_SIZE - self.total_size;
+        let mut temp_file = NamedTempFile::new()?;
+        let size = io::copy(&mut entry.take(remaining_size + 1), &mut temp_file).map_err(|e| {
+            AppError::InvalidArchive(format!("Failed to read {}: {}", file_name, e))
+        })?;
+        if size > remaining_size {
+            return Err(AppError::InvalidArchive(format!(
+                "Archive is too large once extracted. Maximum is {}MB",
+                MAX_TOTAL_SIZE / 1024 / 1024
+            )));
+        }
+        self.total_size += size;
+
+        let content_type = content_type::detect(&file_name, temp_file.path())?;
+
+        self.files.push(TempFile {
+            file: temp_file,
+            content_type: Some(content_type),
+            file_name: Some(file_name),
+            size: size as usize,
+        });
+
+        Ok(())
+    }
+
+    pub fn into_files(self) -> Vec<TempFile> {
+        self.files
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_path_keeps_relative_paths() {
+        assert_eq!(
+            normalize_path("docs/index.html").unwrap(),
+            "docs/index.html"
+        );
+        assert_eq!(normalize_path("./docs//a.css").unwrap(), "docs/a.css");
+        assert_eq!(
+            normalize_path("docs\\img\\a.png").unwrap(),
+            "docs/img/a.png"
+        );
+    }
+
+    #[test]
+    fn normalize_path_rejects_escaping_paths() {
+        for path in [
+            "../index.html",
+            "docs/../../index.html",
+            "..\\index.html",
+            "/etc/passwd",
+            "\\etc\\passwd",
+            "C:/Windows/win.ini",
+            "C:\\Windows\\win.ini",
+            "docs/a\nb.html",
+            "docs/a\u{0}.html",
+            "",
+            "./",
+        ] {
+            assert!(normalize_path(path).is_err(), "{:?} was accepted", path);
+        }
+    }
+
+    #[test]
+    fn extraction_rejects_entries_past_the_total_size() {
+        let mut extraction = Extraction {
+            files: Vec::new(),
+            total_size: MAX_TOTAL_SIZE - 10,
+        };
+
+        extraction.add(&[0u8; 10][..], "a.txt".to_string()).unwrap();
+        let error = extraction.add(&[0u8; 1][..], "b.txt".to_string());
+        assert!(matches!(error, Err(AppError::InvalidArchive(_))));
+    }
+
+    #[test]
+    fn extract_archive_rejects_unknown_formats() {
+        let mut file = tempfile::tempfile().unwrap();
+        io::Write::write_all(&mut file, b"not an archive").unwrap();
+        file.rewind().unwrap();
+
+        assert!(matches!(
+            extract_archive(file),
+            Err(AppError::Validation(_))
+        ));
+    }
+}
@@ -0,0 +1,339 @@
+use std::str::FromStr;
+
+use actix_web::http::header::HeaderValue;
+use mime_guess::mime::{self, Mime};
+use serde::Deserialize;
+
+use crate::error::AppError;
+use crate::utils::redirects::CONFIG_FILE;
+
+/// Policy of fingerprinted assets, their content never changes under a name.
+pub const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Policy of HTML pages, revalidated on every request so a new deployment
+/// is picked up right away.
+pub const NO_CACHE: &str = "no-cache";
+
+/// Overrides the cache policy of the files matching a glob.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rule {
+    pub glob: String,
+    pub value: String,
+}
+
+impl Rule {
+    fn validate(&self) -> Result<(), String> {
+        if self.glob.trim_start_matches('/').is_empty() {
+            return Err("Glob is empty".to_string());
+        }
+        if self.glob.len() > MAX_GLOB_LENGTH {
+            return Err(format!(
+                "Glob is too long. Maximum length is {} characters",
+                MAX_GLOB_LENGTH
+            ));
+        }
+        if tokenize(self.glob.as_bytes())
+            .iter()
+            .filter(|token| !matches!(token, Token::Literal(_)))
+            .count()
+            > MAX_WILDCARDS
+        {
+            return Err(format!(
+                "{} has too many wildcards. Maximum is {}",
+                self.glob, MAX_WILDCARDS
+            ));
+        }
+        if self.value.is_empty() {
+            return Err(format!("Value of {} is empty", self.glob));
+        }
+        HeaderValue::from_str(&self.value)
+            .map_err(|_| format!("Invalid value for {}", self.glob))?;
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct ProjectConfig {
+    #[serde(default)]
+    cache_control: Vec<Rule>,
+}
+
+/// Parses the rules listed under `cache_control` in a `nanohost.json` file.
+pub fn parse_config(content: &str) -> Result<Vec<Rule>, AppError> {
+    let config: ProjectConfig = serde_json::from_str(content)
+        .map_err(|e| AppError::Validation(format!("Invalid {}: {}", CONFIG_FILE, e)))?;
+
+    for (index, rule) in config.cache_control.iter().enumerate() {
+        rule.validate().map_err(|reason| {
+            AppError::Validation(format!(
+                "Invalid cache rule {} of {}: {}",
+                index + 1,
+                CONFIG_FILE,
+                reason
+            ))
+        })?;
+    }
+
+    Ok(config.cache_control)
+}
+
+/// Longest glob of a rule, and most wildcards (`*`, `**` or `?`) in it.
+const MAX_GLOB_LENGTH: usize = 256;
+const MAX_WILDCARDS: usize = 16;
+
+enum Token {
+    Literal(u8),
+    /// `?`
+    AnyChar,
+    /// `*`
+    Star,
+    /// `**`
+    DoubleStar,
+    /// `**/`, which also matches no directory at all
+    Directories,
+}
+
+fn tokenize(glob: &[u8]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < glob.len() {
+        let (token, length) = match &glob[index..] {
+            [b'*', b'*', b'/', ..] => (Token::Directories, 3),
+            [b'*', b'*', ..] => (Token::DoubleStar, 2),
+            [b'*', ..] => (Token::Star, 1),
+            [b'?', ..] => (Token::AnyChar, 1),
+            [c, ..] => (Token::Literal(*c), 1),
+            [] => break,
+        };
+        tokens.push(token);
+        index += length;
+    }
+
+    tokens
+}
+
+/// Matches a file name against a glob: `*` matches within a directory,
+/// `**` across directories and `?` a single character. Runs in
+/// O(glob × name), tracking every position of the name the glob can reach.
+pub fn matches_glob(glob: &str, file_name: &str) -> bool {
+    let name = file_name.trim_start_matches('/').as_bytes();
+    let mut reachable = vec![false; name.len() + 1];
+    reachable[0] = true;
+
+    for token in tokenize(glob.trim_start_matches('/').as_bytes()) {
+        let mut next = vec![false; name.len() + 1];
+        match token {
+            Token::Literal(c) => {
+                for index in 0..name.len() {
+                    next[index + 1] = reachable[index] && name[index] == c;
+                }
+            }
+            Token::AnyChar => {
+                for index in 0..name.len() {
+                    next[index + 1] = reachable[index] && name[index] != b'/';
+                }
+            }
+            Token::Star => {
+                next[0] = reachable[0];
+                for index in 1..=name.len() {
+                    next[index] = reachable[index] || (next[index - 1] && name[index - 1] != b'/');
+                }
+            }
+            Token::DoubleStar => {
+                next[0] = reachable[0];
+                for index in 1..=name.len() {
+                    next[index] = reachable[index] || next[index - 1];
+                }
+            }
+            Token::Directories => {
+                let mut seen = reachable[0];
+                next[0] = reachable[0];
+                for index in 1..=name.len() {
+                    next[index] = reachable[index] || (seen && name[index - 1] == b'/');
+                    seen |= reachable[index];
+                }
+            }
+        }
+
+        if !next.contains(&true) {
+            return false;
+        }
+        reachable = next;
+    }
+
+    reachable[name.len()]
+}
+
+/// Whether a part of a file name looks like a content hash, e.g. the
+/// `3f9a1c` of `app.3f9a1c.js` or the `BdH3kP9x` of `index-BdH3kP9x.js`.
+fn is_hash(part: &str) -> bool {
+    let has_digit = part.chars().any(|c| c.is_ascii_digit());
+    let is_hex = part.len() >= 6
+        && part
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        && part.chars().any(|c| c.is_ascii_alphabetic());
+    // Mixed case tells base64 hashes apart from words such as `v2component`
+    let is_base64 = (8..=16).contains(&part.len())
+        && part.chars().all(|c| c.is_ascii_alphanumeric())
+        && part.chars().any(|c| c.is_ascii_uppercase())
+        && part.chars().any(|c| c.is_ascii_lowercase());
+
+    has_digit && (is_hex || is_base64)
+}
+
+/// Whether the name of a file contains a content hash, so a new version
+/// of the file always gets a new name. The hash has to follow the name as
+/// its own segment (`name.HASH.ext` or `name-HASH.ext`), a stem such as
+/// `MyPhoto2024` or `cafe12` is never taken for one.
+pub fn is_fingerprinted(file_name: &str) -> bool {
+    let base_name = file_name.rsplit('/').next().unwrap_or(file_name);
+    let Some((stem, _extension)) = base_name.rsplit_once('.') else {
+        return false;
+    };
+
+    let mut segments = stem.split(['.', '-', '_']);
+    let has_name = segments.next().is_some_and(|name| !name.is_empty());
+    has_name && segments.any(is_hash)
+}
+
+/// Cache policy of a file: the value of the last rule matching its name,
+/// else immutable for fingerprinted assets and `no-cache` for HTML pages.
+pub fn cache_control_for(rules: &[Rule], file_name: &str, content_type: &str) -> Option<String> {
+    if let Some(rule) = rules
+        .iter()
+        .rev()
+        .find(|rule| matches_glob(&rule.glob, file_name))
+    {
+        return Some(rule.value.clone());
+    }
+
+    let is_html = Mime::from_str(content_type)
+        .is_ok_and(|content_type| content_type.essence_str() == mime::TEXT_HTML.essence_str());
+    if is_html {
+        Some(NO_CACHE.to_string())
+    } else if is_fingerprinted(file_name) {
+        Some(IMMUTABLE.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn star_stays_within_a_directory() {
+        assert!(matches_glob("*.js", "app.js"));
+        assert!(matches_glob("/*.js", "app.js"));
+        assert!(matches_glob("assets/*.js", "assets/app.js"));
+        assert!(matches_glob("assets/*", "assets/"));
+        assert!(!matches_glob("*.js", "assets/app.js"));
+        assert!(!matches_glob("assets/*.js", "assets/vendor/app.js"));
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        assert!(matches_glob("**/*.js", "app.js"));
+        assert!(matches_glob("**/*.js", "assets/vendor/app.js"));
+        assert!(matches_glob("assets/**/*.css", "assets/a.css"));
+        assert!(matches_glob("assets/**/*.css", "assets/x/y/a.css"));
+        assert!(!matches_glob("assets/**/*.css", "other/a.css"));
+        assert!(matches_glob("assets/**", "assets/x/y/a.css"));
+        assert!(matches_glob("**.map", "assets/app.js.map"));
+        assert!(!matches_glob("**/a.css", "xa.css"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(matches_glob("v?.js", "v1.js"));
+        assert!(!matches_glob("v?.js", "v10.js"));
+        assert!(!matches_glob("a?b", "a/b"));
+    }
+
+    #[test]
+    fn pathological_globs_match_in_linear_time() {
+        let glob = "**a**a**a**a**a**a**a**a**b";
+        let name = "a".repeat(10_000);
+
+        let started = Instant::now();
+        assert!(!matches_glob(glob, &name));
+        assert!(!matches_glob(&"*a".repeat(8), &"a".repeat(7)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn rejects_long_globs_and_many_wildcards() {
+        assert!(
+            parse_config(r#"{"cache_control": [{"glob": "**/*.js", "value": "no-store"}]}"#)
+                .is_ok()
+        );
+
+        let long_glob = "a".repeat(MAX_GLOB_LENGTH + 1);
+        let config = format!(
+            r#"{{"cache_control": [{{"glob": "{}", "value": "no-store"}}]}}"#,
+            long_glob
+        );
+        assert!(parse_config(&config).is_err());
+
+        let config = format!(
+            r#"{{"cache_control": [{{"glob": "{}", "value": "no-store"}}]}}"#,
+            "*a".repeat(MAX_WILDCARDS + 1)
+        );
+        assert!(parse_config(&config).is_err());
+    }
+
+    #[test]
+    fn resolves_the_cache_policy() {
+        let rules = vec![Rule {
+            glob: "**/*.js".to_string(),
+            value: "no-store".to_string(),
+        }];
+
+        assert_eq!(
+            cache_control_for(&rules, "app.3f9a1c.js", "application/javascript").as_deref(),
+            Some("no-store")
+        );
+        assert_eq!(
+            cache_control_for(&[], "index.html", "text/html; charset=utf-8").as_deref(),
+            Some(NO_CACHE)
+        );
+        assert_eq!(
+            cache_control_for(&[], "index-BdH3kP9x.css", "text/css").as_deref(),
+            Some(IMMUTABLE)
+        );
+        assert_eq!(cache_control_for(&[], "logo.png", "image/png"), None);
+    }
+
+    #[test]
+    fn detects_fingerprinted_names() {
+        for file_name in [
+            "app.3f9a1c.js",
+            "assets/index-BdH3kP9x.js",
+            "main.3f9a1c2b.chunk.js",
+            "vendor_a1b2c3d4.css",
+        ] {
+            assert!(is_fingerprinted(file_name), "{} wasn't detected", file_name);
+        }
+
+        for file_name in [
+            "MyPhoto2024.jpg",
+            "Logo2Dark.svg",
+            "iPhone15Pro.png",
+            "cafe12.js",
+            "face01.png",
+            "images/deadbeef42.png",
+            ".3f9a1c.js",
+            "-BdH3kP9x.js",
+            "app.js",
+            "app-v2component.js",
+            "logo-dark.png",
+            "3f9a1c",
+        ] {
+            assert!(!is_fingerprinted(file_name), "{} was detected", file_name);
+        }
+    }
+}
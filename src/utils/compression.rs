@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use flate2::{write::GzEncoder, Compression};
+use mime_guess::mime::Mime;
+use tempfile::NamedTempFile;
+
+use crate::utils::content_type::is_text;
+
+/// Smaller files don't gain enough to be worth a variant.
+pub const MIN_COMPRESSED_SIZE: u64 = 1024; // 1KB
+
+/// Larger files would take too long to compress at the highest level.
+pub const MAX_COMPRESSED_SIZE: u64 = 16 * 1024 * 1024; // 16MB
+
+/// Binary types which still compress well, every text type does.
+const COMPRESSIBLE_TYPES: [&str; 5] = [
+    "application/wasm",
+    "font/otf",
+    "font/ttf",
+    "image/bmp",
+    "image/x-icon",
+];
+
+/// Precompressed variants of a file, by order of preference.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 2] = [Encoding::Brotli, Encoding::Gzip];
+
+    /// Value of the `Content-Encoding` header.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Key of the variant. Variants are kept apart from the `sites/` keys of
+    /// uploaded files, so a shipped `app.js.gz` can't be mistaken for (or
+    /// overwritten by) the variant of `app.js`.
+    pub fn variant_key(&self, key: &str) -> String {
+        match self {
+            Encoding::Brotli => format!("variants/br/{}", key),
+            Encoding::Gzip => format!("variants/gz/{}", key),
+        }
+    }
+}
+
+/// Whether variants are generated for a file of this type and size.
+pub fn is_compressible(content_type: &str, size: u64) -> bool {
+    let Ok(content_type) = content_type.parse::<Mime>() else {
+        return false;
+    };
+
+    (MIN_COMPRESSED_SIZE..=MAX_COMPRESSED_SIZE).contains(&size)
+        && (is_text(&content_type) || COMPRESSIBLE_TYPES.contains(&content_type.essence_str()))
+}
+
+/// Compresses a file to a temp file at the highest level of the encoding.
+pub fn compress(path: &Path, encoding: Encoding) -> io::Result<NamedTempFile> {
+    let mut source = File::open(path)?;
+    let mut temp_file = NamedTempFile::new()?;
+
+    match encoding {
+        Encoding::Brotli => {
+            let mut writer = brotli::CompressorWriter::new(&mut temp_file, 4096, 11, 22);
+            io::copy(&mut source, &mut writer)?;
+            writer.flush()?;
+        }
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(&mut temp_file, Compression::best());
+            io::copy(&mut source, &mut encoder)?;
+            encoder.finish()?;
+        }
+    }
+
+    Ok(temp_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Deployment;
+
+    #[test]
+    fn variant_keys_dont_collide_with_uploaded_files() {
+        let deployment_path = Deployment::path_for("site", "deployment");
+        let uploaded_keys: Vec<String> = ["app.js", "app.js.br", "app.js.gz", "variants/br/app.js"]
+            .iter()
+            .map(|name| format!("{}{}", deployment_path, name))
+            .collect();
+
+        for key in &uploaded_keys {
+            for encoding in Encoding::ALL {
+                let variant_key = encoding.variant_key(key);
+                assert!(
+                    !uploaded_keys.contains(&variant_key),
+                    "{} collides with an uploaded file",
+                    variant_key
+                );
+                assert!(!variant_key.starts_with(&deployment_path));
+            }
+        }
+        assert_ne!(
+            Encoding::Brotli.variant_key(&uploaded_keys[0]),
+            Encoding::Gzip.variant_key(&uploaded_keys[0])
+        );
+    }
+}
@@ -0,0 +1,352 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use mime_guess::{from_path, mime, Mime};
+
+use crate::config::Config;
+use crate::error::AppError;
+
+/// Bytes read from the start of a file to sniff its type and charset.
+const SNIFF_SIZE: u64 = 64 * 1024; // 64KB
+
+/// Magic bytes of the binary formats commonly found on static sites.
+const SIGNATURES: [(&[u8], &str); 10] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+    (b"\0asm", "application/wasm"),
+    (b"\x00\x00\x01\x00", "image/x-icon"),
+    (b"PK\x03\x04", "application/zip"),
+];
+
+/// Guesses the type of content without a known extension from its first bytes.
+fn sniff(head: &[u8]) -> Mime {
+    if let Some((_, content_type)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+    {
+        return content_type
+            .parse()
+            .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+    }
+    if head.starts_with(b"RIFF") && head.get(8..12) == Some(b"WEBP") {
+        return "image/webp"
+            .parse()
+            .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+    }
+
+    if head.contains(&0) && !head.starts_with(b"\xff\xfe") && !head.starts_with(b"\xfe\xff") {
+        return mime::APPLICATION_OCTET_STREAM;
+    }
+
+    let start = String::from_utf8_lossy(&head[..head.len().min(512)])
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .to_ascii_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        mime::TEXT_HTML
+    } else if start.starts_with("<svg") || (start.starts_with("<?xml") && start.contains("<svg")) {
+        mime::IMAGE_SVG
+    } else if start.starts_with("<?xml") {
+        mime::TEXT_XML
+    } else {
+        mime::TEXT_PLAIN
+    }
+}
+
+pub fn is_text(content_type: &Mime) -> bool {
+    content_type.type_() == mime::TEXT
+        || content_type.suffix() == Some(mime::JSON)
+        || content_type.suffix() == Some(mime::XML)
+        || matches!(
+            content_type.essence_str(),
+            "application/javascript" | "application/json" | "application/xml"
+        )
+}
+
+/// Charsets a declaration can name, anything else is ignored.
+const KNOWN_CHARSETS: [&str; 35] = [
+    "utf-8",
+    "utf-16",
+    "utf-16le",
+    "utf-16be",
+    "us-ascii",
+    "iso-8859-1",
+    "iso-8859-2",
+    "iso-8859-3",
+    "iso-8859-4",
+    "iso-8859-5",
+    "iso-8859-6",
+    "iso-8859-7",
+    "iso-8859-8",
+    "iso-8859-9",
+    "iso-8859-10",
+    "iso-8859-13",
+    "iso-8859-14",
+    "iso-8859-15",
+    "iso-8859-16",
+    "windows-1250",
+    "windows-1251",
+    "windows-1252",
+    "windows-1253",
+    "windows-1254",
+    "windows-1255",
+    "windows-1256",
+    "windows-1257",
+    "windows-1258",
+    "koi8-r",
+    "koi8-u",
+    "shift_jis",
+    "euc-jp",
+    "euc-kr",
+    "gb18030",
+    "big5",
+];
+
+/// Value of `name=` in a tag or declaration, quoted or not.
+fn attribute_value<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let value = text[text.find(name)? + name.len()..]
+        .trim_start()
+        .strip_prefix('=')?
+        .trim_start()
+        .trim_start_matches(['"', '\'']);
+    let end = value
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(value.len());
+
+    Some(&value[..end])
+}
+
+/// Charset declared by the content: a `<meta charset>` or `<meta content>`
+/// of HTML pages, the `@charset` rule of stylesheets or the `encoding` of the
+/// XML declaration. Other types can't declare one, and unknown charsets are
+/// ignored.
+fn declared_charset(head: &[u8], content_type: &Mime) -> Option<String> {
+    let head = String::from_utf8_lossy(&head[..head.len().min(1024)])
+        .trim_start_matches('\u{feff}')
+        .to_ascii_lowercase();
+
+    let charset = if content_type.essence_str() == mime::TEXT_HTML.essence_str() {
+        head.split("<meta")
+            .skip(1)
+            .filter_map(|meta| attribute_value(meta.split('>').next()?, "charset"))
+            .next()?
+    } else if content_type.essence_str() == mime::TEXT_CSS.essence_str() {
+        // The rule must be the very first bytes of the stylesheet, with double quotes
+        let rest = head.strip_prefix("@charset \"")?;
+        &rest[..rest.find('"')?]
+    } else if content_type.suffix() == Some(mime::XML) || content_type.subtype() == mime::XML {
+        let declaration = head.strip_prefix("<?xml")?;
+        attribute_value(&declaration[..declaration.find("?>")?], "encoding")?
+    } else {
+        return None;
+    };
+
+    KNOWN_CHARSETS
+        .contains(&charset)
+        .then(|| charset.to_string())
+}
+
+/// Charset of text content: its byte order mark, else the declared one,
+/// else UTF-8 when the content is valid UTF-8, else Windows-1252.
+fn detect_charset(head: &[u8], content_type: &Mime, is_complete: bool) -> String {
+    if head.starts_with(b"\xef\xbb\xbf") {
+        return "utf-8".to_string();
+    }
+    if head.starts_with(b"\xff\xfe") {
+        return "utf-16le".to_string();
+    }
+    if head.starts_with(b"\xfe\xff") {
+        return "utf-16be".to_string();
+    }
+    if let Some(charset) = declared_charset(head, content_type) {
+        return charset;
+    }
+
+    match std::str::from_utf8(head) {
+        Ok(_) => "utf-8".to_string(),
+        // The sniffed bytes may end in the middle of a character
+        Err(e) if e.error_len().is_none() && !is_complete => "utf-8".to_string(),
+        Err(_) => "windows-1252".to_string(),
+    }
+}
+
+/// Resolves the content type of a file from its extension, or its content
+/// when the extension is unknown. Text types get their charset.
+pub fn detect(file_name: &str, path: &Path) -> io::Result<Mime> {
+    let mut head = Vec::new();
+    File::open(path)?.take(SNIFF_SIZE).read_to_end(&mut head)?;
+    let is_complete = (head.len() as u64) < SNIFF_SIZE;
+
+    let content_type = from_path(file_name).first().unwrap_or_else(|| sniff(&head));
+    if !is_text(&content_type) {
+        return Ok(content_type);
+    }
+
+    let content_type = format!(
+        "{}; charset={}",
+        content_type.essence_str(),
+        detect_charset(&head, &content_type, is_complete)
+    );
+    Ok(content_type
+        .parse()
+        .unwrap_or(mime::APPLICATION_OCTET_STREAM))
+}
+
+/// Content types accepted for a site type. Patterns are either a full type
+/// (`text/html`) or a whole top-level type (`image/*`).
+#[derive(Debug, Clone)]
+pub struct TypePolicy {
+    /// Every type is allowed when empty
+    allowed: Vec<String>,
+    denied: Vec<String>,
+}
+
+fn matches_pattern(pattern: &str, content_type: &Mime) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(top_level) => top_level == "*" || content_type.type_().as_str() == top_level,
+        None => pattern == content_type.essence_str(),
+    }
+}
+
+impl TypePolicy {
+    pub fn new(allowed: Vec<String>, denied: Vec<String>) -> TypePolicy {
+        TypePolicy { allowed, denied }
+    }
+
+    pub fn check(&self, file_name: &str, content_type: &Mime) -> Result<(), AppError> {
+        let is_denied = self
+            .denied
+            .iter()
+            .any(|pattern| matches_pattern(pattern, content_type));
+        let is_allowed = self.allowed.is_empty()
+            || self
+                .allowed
+                .iter()
+                .any(|pattern| matches_pattern(pattern, content_type));
+
+        if is_denied || !is_allowed {
+            return Err(AppError::Validation(format!(
+                "Files of type {} are not allowed: {}",
+                content_type.essence_str(),
+                file_name
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Content types accepted for each site type.
+#[derive(Debug, Clone)]
+pub struct UploadPolicy {
+    pub html: TypePolicy,
+    pub archive: TypePolicy,
+}
+
+impl UploadPolicy {
+    pub fn from_config(config: &Config) -> UploadPolicy {
+        UploadPolicy {
+            html: TypePolicy::new(
+                config.html_allowed_types.clone(),
+                config.html_denied_types.clone(),
+            ),
+            archive: TypePolicy::new(
+                config.archive_allowed_types.clone(),
+                config.archive_denied_types.clone(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn charset_of(content: &str, content_type: &str) -> String {
+        detect_charset(content.as_bytes(), &content_type.parse().unwrap(), true)
+    }
+
+    #[test]
+    fn reads_html_declarations() {
+        assert_eq!(
+            charset_of("<html><head><meta charset=\"ISO-8859-1\">", "text/html"),
+            "iso-8859-1"
+        );
+        assert_eq!(
+            charset_of(
+                "<meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1251\">",
+                "text/html"
+            ),
+            "windows-1251"
+        );
+        assert_eq!(
+            charset_of("<p>Set the charset=latin1 option</p>", "text/html"),
+            "utf-8"
+        );
+    }
+
+    #[test]
+    fn reads_css_and_xml_declarations() {
+        assert_eq!(
+            charset_of("@charset \"iso-8859-15\";\nbody {}", "text/css"),
+            "iso-8859-15"
+        );
+        assert_eq!(
+            charset_of("body {}\n@charset \"iso-8859-15\";", "text/css"),
+            "utf-8"
+        );
+        assert_eq!(
+            charset_of(
+                "<?xml version=\"1.0\" encoding=\"ISO-8859-2\"?><svg/>",
+                "image/svg+xml"
+            ),
+            "iso-8859-2"
+        );
+    }
+
+    #[test]
+    fn ignores_charsets_outside_declarations() {
+        assert_eq!(
+            charset_of("var charset = foo;", "application/javascript"),
+            "utf-8"
+        );
+        assert_eq!(
+            charset_of("{\"charset\": \"koi8-r\"}", "application/json"),
+            "utf-8"
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_charsets() {
+        assert_eq!(charset_of("<meta charset=\"foo\">", "text/html"), "utf-8");
+        assert_eq!(charset_of("<meta charset=\"\">", "text/html"), "utf-8");
+    }
+
+    #[test]
+    fn falls_back_to_the_content() {
+        let utf16 = [0xff, 0xfe, b'a', 0];
+        assert_eq!(detect_charset(&utf16, &mime::TEXT_PLAIN, true), "utf-16le");
+        assert_eq!(
+            detect_charset(b"caf\xe9", &mime::TEXT_PLAIN, true),
+            "windows-1252"
+        );
+        assert_eq!(
+            detect_charset(b"caf\xc3", &mime::TEXT_PLAIN, false),
+            "utf-8"
+        );
+    }
+
+    #[test]
+    fn sniffs_unknown_extensions() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n...").essence_str(), "image/png");
+        assert_eq!(sniff(b"<!DOCTYPE html><html>"), mime::TEXT_HTML);
+        assert_eq!(sniff(b"plain text"), mime::TEXT_PLAIN);
+        assert_eq!(sniff(b"\x01\x00\x02"), mime::APPLICATION_OCTET_STREAM);
+    }
+}
@@ -0,0 +1,219 @@
+use actix_multipart::form::tempfile::TempFile;
+
+use crate::error::AppError;
+use crate::utils::cache_control;
+use crate::utils::headers::{self, HEADERS_FILE};
+use crate::utils::redirects::{self, CONFIG_FILE, REDIRECTS_FILE};
+
+/// Maximum size of a configuration file.
+const MAX_CONFIG_FILE_SIZE: usize = 64 * 1024; // 64KB
+
+/// Maximum number of rules of each kind in a deployment.
+const MAX_RULES: usize = 1000;
+
+/// Rules are published to the edge as lines, and a value of the CloudFront
+/// key value store can't exceed 1KB.
+const MAX_EDGE_VALUE_SIZE: usize = 1024;
+pub const MAX_LINE_LENGTH: usize = 1000;
+
+/// Redirect, header and cache rules read from the configuration files of a deployment.
+#[derive(Default)]
+pub struct DeploymentConfig {
+    pub redirects: Vec<redirects::Rule>,
+    pub headers: Vec<headers::Rule>,
+    /// Only used to resolve the cache policy of each file when deploying
+    pub cache_control: Vec<cache_control::Rule>,
+}
+
+/// Removes a root file from the uploaded files and returns its content.
+fn take_file(files: &mut Vec<TempFile>, file_name: &str) -> Result<Option<String>, AppError> {
+    let Some(position) = files
+        .iter()
+        .position(|file| file.file_name.as_deref() == Some(file_name))
+    else {
+        return Ok(None);
+    };
+
+    let file = files.remove(position);
+    if file.size > MAX_CONFIG_FILE_SIZE {
+        return Err(AppError::Validation(format!(
+            "{} is too large. Maximum size is {}KB",
+            file_name,
+            MAX_CONFIG_FILE_SIZE / 1024
+        )));
+    }
+
+    let content = std::fs::read_to_string(file.file.path())
+        .map_err(|_| AppError::Validation(format!("{} must be UTF-8 text", file_name)))?;
+    Ok(Some(content))
+}
+
+/// Splits lines into values small enough for the key value store: `key`
+/// holds the number of chunks and `{key}:{n}` the lines, in order.
+fn chunked_entries(key: &str, lines: impl Iterator<Item = String>) -> Vec<(String, String)> {
+    let mut chunks: Vec<String> = Vec::new();
+    for line in lines {
+        match chunks.last_mut() {
+            Some(chunk) if chunk.len() + line.len() < MAX_EDGE_VALUE_SIZE => {
+                chunk.push('\n');
+                chunk.push_str(&line);
+            }
+            _ => chunks.push(line),
+        }
+    }
+
+    let mut entries = vec![(key.to_string(), chunks.len().to_string())];
+    entries.extend(
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| (format!("{}:{}", key, index), chunk)),
+    );
+
+    entries
+}
+
+impl DeploymentConfig {
+    /// Takes `_redirects`, `_headers` and `nanohost.json` out of the uploaded
+    /// files, so they aren't served, and parses their rules. Rules of
+    /// `_redirects` come before the ones of `nanohost.json`.
+    pub fn take(files: &mut Vec<TempFile>) -> Result<DeploymentConfig, AppError> {
+        let mut config = DeploymentConfig::default();
+        if let Some(content) = take_file(files, REDIRECTS_FILE)? {
+            config
+                .redirects
+                .extend(redirects::parse_redirects(&content)?);
+        }
+        if let Some(content) = take_file(files, CONFIG_FILE)? {
+            config.redirects.extend(redirects::parse_config(&content)?);
+            config.cache_control = cache_control::parse_config(&content)?;
+        }
+        if let Some(content) = take_file(files, HEADERS_FILE)? {
+            config.headers = headers::parse_headers(&content)?;
+        }
+
+        if [
+            config.redirects.len(),
+            config.headers.len(),
+            config.cache_control.len(),
+        ]
+        .iter()
+        .any(|&count| count > MAX_RULES)
+        {
+            return Err(AppError::Validation(format!(
+                "Too many redirect, header or cache rules. Maximum is {} of each",
+                MAX_RULES
+            )));
+        }
+
+        Ok(config)
+    }
+
+    /// Whether there's no rule to publish to the edge.
+    pub fn is_empty(&self) -> bool {
+        self.redirects.is_empty() && self.headers.is_empty()
+    }
+
+    /// Entries of the CloudFront key value store holding the rules of a
+    /// deployment, under `{deployment_id}:redirects` and `{deployment_id}:headers`.
+    pub fn edge_entries(&self, deployment_id: &str) -> Vec<(String, String)> {
+        let mut entries = Vec::new();
+        if !self.redirects.is_empty() {
+            entries.extend(chunked_entries(
+                &format!("{}:redirects", deployment_id),
+                self.redirects.iter().map(redirects::Rule::line),
+            ));
+        }
+        if !self.headers.is_empty() {
+            entries.extend(chunked_entries(
+                &format!("{}:headers", deployment_id),
+                self.headers.iter().map(headers::Rule::line),
+            ));
+        }
+
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn temp_file(file_name: &str, content: &str) -> TempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        TempFile {
+            file,
+            content_type: None,
+            file_name: Some(file_name.to_string()),
+            size: content.len(),
+        }
+    }
+
+    #[test]
+    fn takes_the_configuration_files() {
+        let mut files = vec![
+            temp_file("index.html", "<h1>home</h1>"),
+            temp_file(REDIRECTS_FILE, "/a /b\n"),
+            temp_file(HEADERS_FILE, "/*\n  X-Frame-Options: DENY\n"),
+        ];
+
+        let config = DeploymentConfig::take(&mut files).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(config.redirects.len(), 1);
+        assert_eq!(config.headers.len(), 1);
+    }
+
+    #[test]
+    fn rejects_too_many_rules() {
+        let content: String = (0..=MAX_RULES)
+            .map(|index| format!("/{} /\n", index))
+            .collect();
+        let mut files = vec![temp_file(REDIRECTS_FILE, &content)];
+        assert!(DeploymentConfig::take(&mut files).is_err());
+
+        let content: String = (0..MAX_RULES)
+            .map(|index| format!("/{} /\n", index))
+            .collect();
+        let mut files = vec![temp_file(REDIRECTS_FILE, &content)];
+        assert_eq!(
+            DeploymentConfig::take(&mut files).unwrap().redirects.len(),
+            MAX_RULES
+        );
+    }
+
+    #[test]
+    fn chunks_stay_under_the_edge_value_size() {
+        let lines: Vec<String> = (0..500)
+            .map(|index| format!("/page-{} /target-{} 301", index, index))
+            .collect();
+
+        let entries = chunked_entries("d:redirects", lines.clone().into_iter());
+        let (count_key, count) = &entries[0];
+        assert_eq!(count_key, "d:redirects");
+        assert_eq!(count.parse::<usize>().unwrap(), entries.len() - 1);
+
+        for (index, (key, chunk)) in entries[1..].iter().enumerate() {
+            assert_eq!(key, &format!("d:redirects:{}", index));
+            assert!(chunk.len() <= MAX_EDGE_VALUE_SIZE, "{} bytes", chunk.len());
+        }
+
+        let joined: Vec<&str> = entries[1..]
+            .iter()
+            .flat_map(|(_, chunk)| chunk.lines())
+            .collect();
+        assert_eq!(joined, lines);
+    }
+
+    #[test]
+    fn longest_lines_fit_in_a_chunk() {
+        let line = "x".repeat(MAX_LINE_LENGTH);
+        let entries = chunked_entries("d:headers", vec![line.clone(), line].into_iter());
+        assert_eq!(entries[0].1, "2");
+        assert!(entries[1..]
+            .iter()
+            .all(|(_, chunk)| chunk.len() <= MAX_EDGE_VALUE_SIZE));
+    }
+}
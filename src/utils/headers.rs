@@ -0,0 +1,240 @@
+use std::str::FromStr;
+
+use actix_web::http::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use mime_guess::mime::Mime;
+
+use crate::error::AppError;
+use crate::models::HeaderRule;
+use crate::utils::deployment_config::MAX_LINE_LENGTH;
+use crate::utils::redirects::match_path;
+
+/// Netlify style headers file: a path on its own line followed by indented
+/// `Name: value` lines applied to the responses of that path.
+pub const HEADERS_FILE: &str = "_headers";
+
+/// Headers computed by the server, or which would break the response.
+const RESERVED_HEADERS: [&str; 8] = [
+    "connection",
+    "content-encoding",
+    "content-length",
+    "host",
+    "keep-alive",
+    "location",
+    "transfer-encoding",
+    "upgrade",
+];
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub path: String,
+    pub name: String,
+    pub value: String,
+}
+
+impl Rule {
+    /// The rule as a `path name: value` line.
+    pub fn line(&self) -> String {
+        format!("{} {}: {}", self.path, self.name, self.value)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !self.path.starts_with('/') {
+            return Err(format!("{} must start with /", self.path));
+        }
+        if self.path.contains(':') {
+            return Err(format!("Placeholders are not supported in {}", self.path));
+        }
+        if self.path.trim_end_matches('*').contains('*') {
+            return Err(format!("A splat can only end the path in {}", self.path));
+        }
+
+        let name = HeaderName::from_str(&self.name)
+            .map_err(|_| format!("Invalid header name {}", self.name))?;
+        if RESERVED_HEADERS.contains(&name.as_str()) {
+            return Err(format!("{} can't be set", self.name));
+        }
+        HeaderValue::from_str(&self.value)
+            .map_err(|_| format!("Invalid value for {}", self.name))?;
+        if name == CONTENT_TYPE && Mime::from_str(&self.value).is_err() {
+            return Err(format!("Invalid content type {}", self.value));
+        }
+
+        if self.line().len() > MAX_LINE_LENGTH {
+            return Err(format!(
+                "Rule is too long. Maximum length is {} characters",
+                MAX_LINE_LENGTH
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&HeaderRule> for Rule {
+    fn from(rule: &HeaderRule) -> Rule {
+        Rule {
+            path: rule.path.clone(),
+            name: rule.name.clone(),
+            value: rule.value.clone(),
+        }
+    }
+}
+
+/// Parses a `_headers` file. Blank lines and `#` comments are skipped.
+pub fn parse_headers(content: &str) -> Result<Vec<Rule>, AppError> {
+    let invalid_rule = |line_number: usize, reason: String| {
+        AppError::Validation(format!(
+            "Invalid rule on line {} of {}: {}",
+            line_number, HEADERS_FILE, reason
+        ))
+    };
+
+    let mut rules = Vec::new();
+    let mut path: Option<&str> = None;
+    for (index, line) in content.lines().enumerate() {
+        let is_indented = line.starts_with(char::is_whitespace);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if !is_indented {
+            if line.contains(char::is_whitespace) {
+                return Err(invalid_rule(
+                    index + 1,
+                    "Paths can't contain whitespace".to_string(),
+                ));
+            }
+            path = Some(line);
+            continue;
+        }
+
+        let path =
+            path.ok_or_else(|| invalid_rule(index + 1, "Headers must follow a path".to_string()))?;
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid_rule(index + 1, "Expected `Name: value`".to_string()))?;
+        let rule = Rule {
+            path: path.to_string(),
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+        };
+        rule.validate()
+            .map_err(|reason| invalid_rule(index + 1, reason))?;
+
+        rules.push(rule);
+    }
+
+    Ok(rules)
+}
+
+/// Headers of the rules matching a path (starting with `/`), a header set
+/// by several rules takes the value of the last one.
+pub fn headers_for(rules: &[Rule], path: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for rule in rules
+        .iter()
+        .filter(|rule| match_path(&rule.path, path).is_some())
+    {
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case(&rule.name));
+        headers.push((rule.name.clone(), rule.value.clone()));
+    }
+
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(path: &str, name: &str, value: &str) -> Rule {
+        Rule {
+            path: path.to_string(),
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_paths_and_headers() {
+        let rules = parse_headers(
+            "# security headers\n\
+             /*\n  X-Frame-Options: DENY\n\
+             \n\
+             /assets/*\n\tCache-Control: public, max-age=60\n  Access-Control-Allow-Origin: *\n",
+        )
+        .unwrap();
+
+        let parsed: Vec<String> = rules.iter().map(Rule::line).collect();
+        assert_eq!(
+            parsed,
+            [
+                "/* X-Frame-Options: DENY",
+                "/assets/* Cache-Control: public, max-age=60",
+                "/assets/* Access-Control-Allow-Origin: *",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        for content in [
+            "  X-Frame-Options: DENY",
+            "/a\n  X-Frame-Options",
+            "/a b\n  X-Frame-Options: DENY",
+            "a\n  X-Frame-Options: DENY",
+            "/a/:id\n  X-Frame-Options: DENY",
+            "/a/*/b\n  X-Frame-Options: DENY",
+            "/a\n  Bad Name: x",
+            "/a\n  Content-Length: 10",
+            "/a\n  Location: /b",
+            "/a\n  Content-Type: not a type",
+        ] {
+            assert!(
+                matches!(parse_headers(content), Err(AppError::Validation(_))),
+                "{:?} was accepted",
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn matches_rules_by_path() {
+        let rules = vec![
+            rule("/*", "X-Frame-Options", "DENY"),
+            rule("/assets/*", "Cache-Control", "max-age=60"),
+            rule("/about", "X-Robots-Tag", "noindex"),
+        ];
+
+        assert_eq!(
+            headers_for(&rules, "/assets/app.js"),
+            [
+                ("X-Frame-Options".to_string(), "DENY".to_string()),
+                ("Cache-Control".to_string(), "max-age=60".to_string()),
+            ]
+        );
+        assert_eq!(headers_for(&rules, "/about/").len(), 2);
+        assert_eq!(headers_for(&rules, "/about/team").len(), 1);
+    }
+
+    #[test]
+    fn last_rule_wins() {
+        let rules = vec![
+            rule("/*", "Cache-Control", "no-cache"),
+            rule("/assets/*", "X-Frame-Options", "DENY"),
+            rule("/assets/*", "cache-control", "max-age=60"),
+        ];
+
+        assert_eq!(
+            headers_for(&rules, "/assets/app.js"),
+            [
+                ("X-Frame-Options".to_string(), "DENY".to_string()),
+                ("cache-control".to_string(), "max-age=60".to_string()),
+            ]
+        );
+        assert_eq!(
+            headers_for(&rules, "/index.html"),
+            [("Cache-Control".to_string(), "no-cache".to_string())]
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::error::AppError;
+
+/// Maximum length of a hostname, and of each of its labels (RFC 1035).
+const MAX_HOSTNAME_LENGTH: usize = 253;
+const MAX_LABEL_LENGTH: usize = 63;
+
+/// Decides whether a label can be used as a site name, e.g. a profanity
+/// filter. Kept behind a trait so other filters can be plugged in.
+pub trait NameFilter: Send + Sync {
+    fn rejects(&self, label: &str) -> bool;
+}
+
+/// Rejects labels containing any of the words.
+pub struct BlockedWords {
+    words: Vec<String>,
+}
+
+impl BlockedWords {
+    pub fn new(words: &[String]) -> BlockedWords {
+        BlockedWords {
+            words: words.iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+}
+
+impl NameFilter for BlockedWords {
+    fn rejects(&self, label: &str) -> bool {
+        self.words.iter().any(|word| label.contains(word.as_str()))
+    }
+}
+
+/// Checks a label against RFC 1123: letters, digits and hyphens, neither
+/// starting nor ending with a hyphen.
+fn validate_label(label: &str) -> Result<(), String> {
+    if label.is_empty() {
+        return Err("Labels can't be empty".to_string());
+    }
+    if label.len() > MAX_LABEL_LENGTH {
+        return Err(format!(
+            "{} is too long. Labels are at most {} characters",
+            label, MAX_LABEL_LENGTH
+        ));
+    }
+    if !label
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(format!(
+            "{} can only contain letters, digits and hyphens",
+            label
+        ));
+    }
+    if label.starts_with('-') || label.ends_with('-') {
+        return Err(format!("{} can't start or end with a hyphen", label));
+    }
+    // `ab--` prefixes are reserved for encodings such as punycode (RFC 5891)
+    if label.get(2..4) == Some("--") && !label.starts_with("xn--") {
+        return Err(format!(
+            "{} can't have hyphens in 3rd and 4th position",
+            label
+        ));
+    }
+
+    Ok(())
+}
+
+/// Accented latin letters, from the Latin-1 Supplement to Latin Extended-B
+/// and the Latin Extended Additional blocks.
+fn is_latin(c: char) -> bool {
+    c.is_ascii_alphabetic() || matches!(c, '\u{c0}'..='\u{24f}' | '\u{1e00}'..='\u{1eff}')
+}
+
+/// Internationalized labels mixing latin and non-latin letters are rejected,
+/// they are the usual way to imitate another name (`pаypal` with a Cyrillic `а`).
+fn validate_script(label: &str) -> Result<(), String> {
+    let (unicode_label, _) = idna::domain_to_unicode(label);
+    let letters = unicode_label.chars().filter(|c| c.is_alphabetic());
+    let (latin, non_latin): (Vec<char>, Vec<char>) = letters.partition(|&c| is_latin(c));
+
+    if !latin.is_empty() && !non_latin.is_empty() {
+        return Err(format!(
+            "{} mixes latin and non-latin letters",
+            unicode_label
+        ));
+    }
+
+    Ok(())
+}
+
+/// Normalizes a hostname to its lowercase ASCII form, internationalized
+/// names being converted to punycode, and validates each of its labels.
+pub fn normalize_hostname(value: &str) -> Result<String, AppError> {
+    let invalid_hostname =
+        |reason: String| AppError::Validation(format!("Invalid hostname {}: {}", value, reason));
+
+    let value = value.trim().trim_end_matches('.');
+    if value.chars().any(char::is_whitespace) {
+        return Err(invalid_hostname(
+            "Hostnames can't contain whitespace".to_string(),
+        ));
+    }
+
+    let hostname = idna::domain_to_ascii(value)
+        .map_err(|_| invalid_hostname("Invalid internationalized name".to_string()))?;
+    if hostname.is_empty() || hostname.len() > MAX_HOSTNAME_LENGTH {
+        return Err(invalid_hostname(format!(
+            "Hostnames are 1 to {} characters long",
+            MAX_HOSTNAME_LENGTH
+        )));
+    }
+
+    for label in hostname.split('.') {
+        validate_label(label).map_err(invalid_hostname)?;
+        validate_script(label).map_err(invalid_hostname)?;
+    }
+
+    Ok(hostname)
+}
+
+/// Names and suffixes a site host can be made of.
+#[derive(Clone)]
+pub struct HostPolicy {
+    suffixes: Vec<String>,
+    reserved_names: Vec<String>,
+    name_filters: Vec<Arc<dyn NameFilter>>,
+}
+
+impl HostPolicy {
+    pub fn from_config(config: &Config) -> HostPolicy {
+        HostPolicy {
+            suffixes: config
+                .site_suffixes
+                .iter()
+                .map(|suffix| suffix.trim().trim_matches('.').to_lowercase())
+                .collect(),
+            reserved_names: config
+                .reserved_names
+                .iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+            name_filters: vec![Arc::new(BlockedWords::new(&config.blocked_words))],
+        }
+    }
+
+    /// Builds the host of a site from its name (a single label) and suffix,
+    /// e.g. `blog` and `.nanohost.dev`.
+    pub fn site_host(&self, name: &str, suffix: &str) -> Result<String, AppError> {
+        let name = normalize_hostname(name)?;
+        if name.contains('.') {
+            return Err(AppError::Validation(format!(
+                "Invalid domain {}: The domain must be a single label, without dots",
+                name
+            )));
+        }
+
+        let suffix = normalize_hostname(suffix.trim().trim_start_matches('.'))?;
+        if !self.suffixes.contains(&suffix) {
+            return Err(AppError::Validation(format!(
+                "Suffix .{} is not allowed. Allowed suffixes are {}",
+                suffix,
+                self.suffixes
+                    .iter()
+                    .map(|suffix| format!(".{}", suffix))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+
+        if self.reserved_names.contains(&name) {
+            return Err(AppError::Validation(format!("Domain {} is reserved", name)));
+        }
+        let (unicode_name, _) = idna::domain_to_unicode(&name);
+        if self
+            .name_filters
+            .iter()
+            .any(|filter| filter.rejects(&name) || filter.rejects(&unicode_name))
+        {
+            return Err(AppError::Validation(format!(
+                "Domain {} is not allowed",
+                unicode_name
+            )));
+        }
+
+        let host = format!("{}.{}", name, suffix);
+        if host.len() > MAX_HOSTNAME_LENGTH {
+            return Err(AppError::Validation(format!(
+                "Host {} is too long. Maximum length is {} characters",
+                host, MAX_HOSTNAME_LENGTH
+            )));
+        }
+
+        Ok(host)
+    }
+
+    /// Normalizes a custom domain, which can't be a site host: those are only
+    /// claimed through a site name, never through a TXT record.
+    pub fn custom_host(&self, value: &str) -> Result<String, AppError> {
+        let host = normalize_hostname(value)?;
+        if !host.contains('.') {
+            return Err(AppError::Validation("Invalid domain".to_string()));
+        }
+
+        if let Some(suffix) = self
+            .suffixes
+            .iter()
+            .find(|suffix| host == **suffix || host.ends_with(&format!(".{}", suffix)))
+        {
+            return Err(AppError::Validation(format!(
+                "Domain {} can't be a custom domain, .{} hosts are site names",
+                host, suffix
+            )));
+        }
+
+        Ok(host)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> HostPolicy {
+        HostPolicy {
+            suffixes: vec!["nanohost.dev".to_string()],
+            reserved_names: vec!["admin".to_string(), "www".to_string()],
+            name_filters: vec![Arc::new(BlockedWords::new(&["Casino".to_string()]))],
+        }
+    }
+
+    #[test]
+    fn custom_hosts_exclude_site_suffixes() {
+        let policy = policy();
+        assert_eq!(
+            policy.custom_host("Blog.Example.org").unwrap(),
+            "blog.example.org"
+        );
+        assert_eq!(
+            policy.custom_host("nanohost.dev.example.org").unwrap(),
+            "nanohost.dev.example.org"
+        );
+        assert_eq!(
+            policy.custom_host("mynanohost.dev").unwrap(),
+            "mynanohost.dev"
+        );
+
+        for host in [
+            "blog",
+            "nanohost.dev",
+            "blog.nanohost.dev",
+            "a.b.NANOHOST.dev.",
+        ] {
+            assert!(
+                matches!(policy.custom_host(host), Err(AppError::Validation(_))),
+                "{:?} was accepted",
+                host
+            );
+        }
+    }
+
+    #[test]
+    fn validates_labels() {
+        assert!(validate_label("blog").is_ok());
+        assert!(validate_label("my-blog-2").is_ok());
+        assert!(validate_label("xn--caf-dma").is_ok());
+        assert!(validate_label(&"a".repeat(MAX_LABEL_LENGTH)).is_ok());
+
+        for label in [
+            "",
+            "-blog",
+            "blog-",
+            "my_blog",
+            "ab--cd",
+            &"a".repeat(MAX_LABEL_LENGTH + 1),
+        ] {
+            assert!(validate_label(label).is_err(), "{:?} was accepted", label);
+        }
+    }
+
+    #[test]
+    fn normalizes_hostnames() {
+        assert_eq!(
+            normalize_hostname(" Blog.Example.ORG. ").unwrap(),
+            "blog.example.org"
+        );
+        assert_eq!(normalize_hostname("café.fr").unwrap(), "xn--caf-dma.fr");
+        assert_eq!(
+            normalize_hostname("xn--caf-dma.fr").unwrap(),
+            "xn--caf-dma.fr"
+        );
+        assert_eq!(idna::domain_to_unicode("xn--caf-dma.fr").0, "café.fr");
+        assert_eq!(
+            normalize_hostname("пример.рф").unwrap(),
+            "xn--e1afmkfd.xn--p1ai"
+        );
+
+        for hostname in ["", "a b.org", "a..org", "-a.org", &"a.".repeat(130)] {
+            assert!(
+                normalize_hostname(hostname).is_err(),
+                "{:?} was accepted",
+                hostname
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_mixed_scripts() {
+        // The second `а` is Cyrillic
+        assert!(normalize_hostname("pаypal.com").is_err());
+        assert!(normalize_hostname("paypal.com").is_ok());
+        assert!(normalize_hostname("ελληνικά.gr").is_ok());
+    }
+
+    #[test]
+    fn builds_site_hosts() {
+        let policy = policy();
+        assert_eq!(
+            policy.site_host("Blog", ".nanohost.dev").unwrap(),
+            "blog.nanohost.dev"
+        );
+        assert_eq!(
+            policy.site_host("blog", "nanohost.dev").unwrap(),
+            "blog.nanohost.dev"
+        );
+        assert_eq!(
+            policy.site_host("café", ".nanohost.dev").unwrap(),
+            "xn--caf-dma.nanohost.dev"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_site_hosts() {
+        let policy = policy();
+        for (name, suffix) in [
+            ("a.b", ".nanohost.dev"),
+            ("blog", ".example.org"),
+            ("blog", ""),
+            ("admin", ".nanohost.dev"),
+            ("WWW", ".nanohost.dev"),
+            ("bestcasino", ".nanohost.dev"),
+            ("-blog", ".nanohost.dev"),
+        ] {
+            assert!(
+                matches!(policy.site_host(name, suffix), Err(AppError::Validation(_))),
+                "{}{} was accepted",
+                name,
+                suffix
+            );
+        }
+    }
+}
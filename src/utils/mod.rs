@@ -1,2 +1,12 @@
+pub mod archive;
+pub mod cache_control;
+pub mod compression;
+pub mod content_type;
+pub mod deployment_config;
+pub mod hash;
+pub mod headers;
+pub mod hostname;
+pub mod redirects;
+pub mod tar;
 pub mod upload_file;
 pub mod zip;
\ No newline at end of file
@@ -0,0 +1,300 @@
+use serde::Deserialize;
+
+use crate::error::AppError;
+use crate::models::RedirectRule;
+use crate::utils::deployment_config::MAX_LINE_LENGTH;
+
+/// Netlify style rules file, one `from to [status][!]` rule per line.
+pub const REDIRECTS_FILE: &str = "_redirects";
+
+/// Project configuration file, rules are listed under `redirects`.
+pub const CONFIG_FILE: &str = "nanohost.json";
+
+fn default_status() -> u16 {
+    301
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rule {
+    pub from: String,
+    pub to: String,
+    #[serde(default = "default_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub force: bool,
+}
+
+impl Rule {
+    /// The rule as a line of a `_redirects` file.
+    pub fn line(&self) -> String {
+        format!(
+            "{} {} {}{}",
+            self.from,
+            self.to,
+            self.status,
+            if self.force { "!" } else { "" }
+        )
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !self.from.starts_with('/') {
+            return Err(format!("{} must start with /", self.from));
+        }
+        if self.from.contains(':') {
+            return Err(format!("Placeholders are not supported in {}", self.from));
+        }
+        if self.from.trim_end_matches('*').contains('*') {
+            return Err(format!("A splat can only end the path in {}", self.from));
+        }
+
+        let is_url = self.to.starts_with("https://") || self.to.starts_with("http://");
+        if !self.to.starts_with('/') && !is_url {
+            return Err(format!("{} must be a path or an http(s) URL", self.to));
+        }
+        if self.to.contains(":splat") && !self.from.ends_with('*') {
+            return Err(format!(
+                "{} uses :splat but {} has no splat",
+                self.to, self.from
+            ));
+        }
+
+        match self.status {
+            301 | 302 => {}
+            200 if is_url => {
+                return Err(format!("Rewrites can't point to another host: {}", self.to));
+            }
+            200 => {}
+            status => {
+                return Err(format!(
+                    "Unsupported status {}. Only 301, 302 and 200 are allowed",
+                    status
+                ));
+            }
+        }
+
+        if [&self.from, &self.to]
+            .iter()
+            .any(|path| path.chars().any(char::is_whitespace))
+        {
+            return Err("Paths can't contain whitespace".to_string());
+        }
+        if self.line().len() > MAX_LINE_LENGTH {
+            return Err(format!(
+                "Rule is too long. Maximum length is {} characters",
+                MAX_LINE_LENGTH
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&RedirectRule> for Rule {
+    fn from(rule: &RedirectRule) -> Rule {
+        Rule {
+            from: rule.from_path.clone(),
+            to: rule.to_path.clone(),
+            status: rule.status as u16,
+            force: rule.force,
+        }
+    }
+}
+
+/// Parses a `_redirects` file. Blank lines and `#` comments are skipped.
+pub fn parse_redirects(content: &str) -> Result<Vec<Rule>, AppError> {
+    let invalid_rule = |line_number: usize, reason: String| {
+        AppError::Validation(format!(
+            "Invalid rule on line {} of {}: {}",
+            line_number, REDIRECTS_FILE, reason
+        ))
+    };
+
+    let mut rules = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (from, to, status) = match fields.as_slice() {
+            [from, to] => (from, to, "301"),
+            [from, to, status] => (from, to, *status),
+            _ => {
+                return Err(invalid_rule(
+                    index + 1,
+                    "Expected `from to [status]`".to_string(),
+                ))
+            }
+        };
+
+        let (status, force) = match status.strip_suffix('!') {
+            Some(status) => (status, true),
+            None => (status, false),
+        };
+        let rule = Rule {
+            from: from.to_string(),
+            to: to.to_string(),
+            status: status
+                .parse()
+                .map_err(|_| invalid_rule(index + 1, format!("Invalid status {}", status)))?,
+            force,
+        };
+        rule.validate()
+            .map_err(|reason| invalid_rule(index + 1, reason))?;
+
+        rules.push(rule);
+    }
+
+    Ok(rules)
+}
+
+#[derive(Deserialize)]
+struct ProjectConfig {
+    #[serde(default)]
+    redirects: Vec<Rule>,
+}
+
+/// Parses the rules of a `nanohost.json` file.
+pub fn parse_config(content: &str) -> Result<Vec<Rule>, AppError> {
+    let config: ProjectConfig = serde_json::from_str(content)
+        .map_err(|e| AppError::Validation(format!("Invalid {}: {}", CONFIG_FILE, e)))?;
+
+    for (index, rule) in config.redirects.iter().enumerate() {
+        rule.validate().map_err(|reason| {
+            AppError::Validation(format!(
+                "Invalid rule {} of {}: {}",
+                index + 1,
+                CONFIG_FILE,
+                reason
+            ))
+        })?;
+    }
+
+    Ok(config.redirects)
+}
+
+fn without_trailing_slash(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+/// Matches a request path (starting with `/`) against a rule path and
+/// returns what the trailing `*` matched. `/blog/*` also matches `/blog`,
+/// and trailing slashes are ignored otherwise.
+pub fn match_path<'a>(pattern: &str, path: &'a str) -> Option<&'a str> {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => match path.strip_prefix(prefix) {
+            Some(splat) => Some(splat),
+            None if without_trailing_slash(prefix) == path => Some(""),
+            None => None,
+        },
+        None if without_trailing_slash(pattern) == without_trailing_slash(path) => Some(""),
+        None => None,
+    }
+}
+
+/// Applies a rule to a request path and returns the target, with `:splat`
+/// replaced by what the `*` of `from` matched.
+pub fn rewrite(from: &str, to: &str, path: &str) -> Option<String> {
+    match_path(from, path).map(|splat| to.replace(":splat", splat))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_statuses_and_forced_rules() {
+        let rules = parse_redirects(
+            "# comment\n\
+             /old /new\n\
+             \n\
+             /temp /elsewhere 302\n\
+             /app/* /index.html 200\n\
+             /docs/* https://docs.example.org/:splat 301!\n",
+        )
+        .unwrap();
+
+        let parsed: Vec<(&str, &str, u16, bool)> = rules
+            .iter()
+            .map(|rule| {
+                (
+                    rule.from.as_str(),
+                    rule.to.as_str(),
+                    rule.status,
+                    rule.force,
+                )
+            })
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                ("/old", "/new", 301, false),
+                ("/temp", "/elsewhere", 302, false),
+                ("/app/*", "/index.html", 200, false),
+                ("/docs/*", "https://docs.example.org/:splat", 301, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        for content in [
+            "/only-one-field",
+            "/a /b 301 extra",
+            "old /new",
+            "/a /b 404",
+            "/a /b abc",
+            "/a/:id /b",
+            "/a/*/b /c",
+            "/a ftp://example.org",
+            "/a /b/:splat",
+            "/a https://example.org 200",
+        ] {
+            let error = parse_redirects(&format!("/ok /fine\n{}", content));
+            match error {
+                Err(AppError::Validation(message)) => {
+                    assert!(message.contains("line 2"), "{}: {}", content, message)
+                }
+                _ => panic!("{:?} was accepted", content),
+            }
+        }
+    }
+
+    #[test]
+    fn parses_config_rules() {
+        let rules = parse_config(
+            r#"{"redirects": [{"from": "/a", "to": "/b"}, {"from": "/c/*", "to": "/d/:splat", "status": 302, "force": true}]}"#,
+        )
+        .unwrap();
+        assert_eq!(rules[0].status, 301);
+        assert_eq!(rules[1].line(), "/c/* /d/:splat 302!");
+
+        assert!(parse_config(r#"{"redirects": [{"from": "a", "to": "/b"}]}"#).is_err());
+        assert!(parse_config("not json").is_err());
+    }
+
+    #[test]
+    fn matches_paths() {
+        assert_eq!(match_path("/about", "/about/"), Some(""));
+        assert_eq!(match_path("/about/", "/about"), Some(""));
+        assert_eq!(match_path("/about", "/about/team"), None);
+        assert_eq!(match_path("/blog/*", "/blog/2024/post"), Some("2024/post"));
+        assert_eq!(match_path("/blog/*", "/blog"), Some(""));
+        assert_eq!(match_path("/blog/*", "/blogs"), None);
+        assert_eq!(match_path("/*", "/anything"), Some("anything"));
+    }
+
+    #[test]
+    fn rewrites_splats() {
+        assert_eq!(
+            rewrite("/blog/*", "/posts/:splat", "/blog/2024/post").as_deref(),
+            Some("/posts/2024/post")
+        );
+        assert_eq!(rewrite("/old", "/new", "/old").as_deref(), Some("/new"));
+        assert_eq!(rewrite("/old", "/new", "/other"), None);
+    }
+}
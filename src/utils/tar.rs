@@ -0,0 +1,158 @@
+use actix_multipart::form::tempfile::TempFile;
+use std::io::Read;
+use tar::{Archive, EntryType};
+
+use crate::error::AppError;
+use crate::utils::archive::{
+    is_ignored, normalize_path, Extraction, MAX_COMPRESSION_RATIO, MIN_RATIO_CHECKED_SIZE,
+};
+
+fn invalid_archive(e: std::io::Error) -> AppError {
+    AppError::InvalidArchive(format!("Invalid tar archive: {}", e))
+}
+
+/// Extracts a tar archive read from `reader`, which may be decompressing it.
+/// `archive_size` is the size of the uploaded file, used to detect bombs since
+/// tarballs are compressed as a whole rather than per entry.
+pub fn extract_tar(reader: impl Read, archive_size: u64) -> Result<Vec<TempFile>, AppError> {
+    let mut archive = Archive::new(reader);
+    let mut extraction = Extraction::default();
+
+    for entry in archive.entries().map_err(invalid_archive)? {
+        let entry = entry.map_err(invalid_archive)?;
+        let path = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous => (),
+            // Directories are implied by the file paths, and extended headers
+            // are already applied to the entries by the tar reader
+            EntryType::Directory
+            | EntryType::XGlobalHeader
+            | EntryType::XHeader
+            | EntryType::GNULongName
+            | EntryType::GNULongLink => continue,
+            EntryType::Symlink | EntryType::Link => {
+                return Err(AppError::InvalidArchive(format!(
+                    "Links are not allowed: {}",
+                    path
+                )));
+            }
+            _ => {
+                return Err(AppError::InvalidArchive(format!(
+                    "Unsupported entry type: {}",
+                    path
+                )));
+            }
+        }
+
+        let file_name = normalize_path(&path)?;
+        if is_ignored(&file_name) {
+            continue;
+        }
+
+        extraction.add(entry, file_name)?;
+
+        if extraction.total_size() >= MIN_RATIO_CHECKED_SIZE
+            && extraction.total_size() / archive_size.max(1) > MAX_COMPRESSION_RATIO
+        {
+            return Err(AppError::InvalidArchive(
+                "Compression ratio of the archive is suspiciously high".to_string(),
+            ));
+        }
+    }
+
+    Ok(extraction.into_files())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+    use tar::{Builder, Header};
+
+    /// Builds a tar archive in memory. Names are written to the header as is,
+    /// since the tar builder refuses the paths these tests need.
+    fn tar_archive(entries: &[(&str, EntryType, &[u8])]) -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        for (name, entry_type, content) in entries {
+            let mut header = Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            if *entry_type == EntryType::Symlink {
+                header.set_link_name("index.html").unwrap();
+            }
+            header.set_cksum();
+            builder.append(&header, *content).unwrap();
+        }
+
+        builder.into_inner().unwrap()
+    }
+
+    fn extract(archive: &[u8]) -> Result<Vec<TempFile>, AppError> {
+        extract_tar(archive, archive.len() as u64)
+    }
+
+    #[test]
+    fn extracts_files() {
+        let archive = tar_archive(&[
+            ("index.html", EntryType::Regular, b"<h1>home</h1>"),
+            ("docs/", EntryType::Directory, b""),
+            ("docs/a.css", EntryType::Regular, b"body {}"),
+        ]);
+
+        let files = extract(&archive).unwrap();
+        let names: Vec<&str> = files
+            .iter()
+            .filter_map(|file| file.file_name.as_deref())
+            .collect();
+        assert_eq!(names, ["index.html", "docs/a.css"]);
+    }
+
+    #[test]
+    fn skips_macos_metadata() {
+        let archive = tar_archive(&[
+            ("index.html", EntryType::Regular, b"x"),
+            ("./__MACOSX/._index.html", EntryType::Regular, b"x"),
+            ("./.DS_Store", EntryType::Regular, b"x"),
+        ]);
+
+        assert_eq!(extract(&archive).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rejects_escaping_paths() {
+        for name in ["../evil.html", "/etc/passwd", "C:/evil.html", "a\u{1}.html"] {
+            let archive = tar_archive(&[(name, EntryType::Regular, b"x")]);
+            assert!(extract(&archive).is_err(), "{:?} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn rejects_links() {
+        for entry_type in [EntryType::Symlink, EntryType::Link] {
+            let archive = tar_archive(&[("link.html", entry_type, b"")]);
+            assert!(matches!(
+                extract(&archive),
+                Err(AppError::InvalidArchive(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_high_compression_ratios() {
+        let zeros = vec![0u8; 2 * MIN_RATIO_CHECKED_SIZE as usize];
+        let archive = tar_archive(&[("zeros.bin", EntryType::Regular, &zeros)]);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&archive).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let decoder = flate2::read::GzDecoder::new(&compressed[..]);
+        assert!(matches!(
+            extract_tar(decoder, compressed.len() as u64),
+            Err(AppError::InvalidArchive(_))
+        ));
+    }
+}
@@ -5,8 +5,12 @@ pub struct UploadedFile {
     pub filename: String,
     pub content_type: String,
     pub size: i64,
+    pub hash: String,
     pub s3_key: String,
     pub s3_url: String,
+    /// Sizes of the precompressed variants uploaded next to the file
+    pub brotli_size: Option<i64>,
+    pub gzip_size: Option<i64>,
 }
 
 impl UploadedFile {
@@ -14,6 +18,7 @@ impl UploadedFile {
         filename: impl Into<String>,
         content_type: impl Into<String>,
         size: i64,
+        hash: impl Into<String>,
         s3_key: impl Into<String>,
         s3_url: impl Into<String>,
     ) -> Self {
@@ -21,8 +26,11 @@ impl UploadedFile {
             filename: filename.into(),
             content_type: content_type.into(),
             size,
+            hash: hash.into(),
             s3_key: s3_key.into(),
             s3_url: s3_url.into(),
+            brotli_size: None,
+            gzip_size: None,
         }
     }
 }
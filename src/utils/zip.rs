@@ -1,44 +1,161 @@
 use actix_multipart::form::tempfile::TempFile;
-use mime_guess::from_path;
 use std::fs::File;
-use std::io::{Read, Write};
-use tempfile::NamedTempFile;
 use zip::read::ZipArchive;
 
-pub fn extract_file(file: File) -> Vec<TempFile> {
-    let mut archive = ZipArchive::new(file).unwrap();
-    let mut files = Vec::new();
+use crate::error::AppError;
+use crate::utils::archive::{
+    is_ignored, normalize_path, Extraction, MAX_COMPRESSION_RATIO, MAX_ENTRIES,
+    MIN_RATIO_CHECKED_SIZE,
+};
+
+fn invalid_archive(e: zip::result::ZipError) -> AppError {
+    AppError::InvalidArchive(format!("Invalid zip file: {}", e))
+}
+
+pub fn extract_file(file: File) -> Result<Vec<TempFile>, AppError> {
+    let mut archive = ZipArchive::new(file).map_err(invalid_archive)?;
+    let mut extraction = Extraction::default();
+
+    // The central directory is read upfront, so oversized archives
+    // can be rejected before anything is extracted
+    if archive.len() > MAX_ENTRIES {
+        return Err(AppError::InvalidArchive(format!(
+            "Archive has too many entries. Maximum is {}",
+            MAX_ENTRIES
+        )));
+    }
 
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i).unwrap();
+        let file = archive.by_index(i).map_err(invalid_archive)?;
 
-        // Skip directories, macOS metadata, and .DS_Store files
-        // .DS_Store files are created by macOS Finder and are not useful for our purposes
-        if file.is_dir()
-            || file.name().starts_with("__MACOSX")
-            || file.name().ends_with(".DS_Store")
-        {
+        if file.is_dir() {
+            continue;
+        }
+
+        let file_name = normalize_path(file.name())?;
+        if is_ignored(&file_name) {
             continue;
         }
 
-        let mut content = Vec::new();
-        file.read_to_end(&mut content).unwrap();
+        if file.is_symlink() {
+            return Err(AppError::InvalidArchive(format!(
+                "Symbolic links are not allowed: {}",
+                file_name
+            )));
+        }
 
-        let mut temp_file = NamedTempFile::new().unwrap();
-        temp_file.write_all(&content).unwrap();
+        if file.size() >= MIN_RATIO_CHECKED_SIZE
+            && file.size() / file.compressed_size().max(1) > MAX_COMPRESSION_RATIO
+        {
+            return Err(AppError::InvalidArchive(format!(
+                "Compression ratio of {} is suspiciously high",
+                file_name
+            )));
+        }
 
-        let file_name = file.name().to_string();
-        let content_type = from_path(&file_name).first_or_octet_stream();
+        extraction.add(file, file_name)?;
+    }
 
-        let temp_file = TempFile {
-            file: temp_file,
-            content_type: Some(content_type),
-            file_name: Some(file_name),
-            size: content.len(),
-        };
+    Ok(extraction.into_files())
+}
 
-        files.push(temp_file);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Seek, Write};
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    /// Builds a zip archive in a temp file, entries with a `None` content
+    /// are symbolic links to `index.html`.
+    fn zip_archive(entries: &[(&str, Option<&[u8]>)]) -> File {
+        let mut writer = ZipWriter::new(tempfile::tempfile().unwrap());
+        let options = SimpleFileOptions::default();
+        for (name, content) in entries {
+            match content {
+                Some(content) => {
+                    writer.start_file(*name, options).unwrap();
+                    writer.write_all(content).unwrap();
+                }
+                None => writer.add_symlink(*name, "index.html", options).unwrap(),
+            }
+        }
+
+        let mut file = writer.finish().unwrap();
+        file.rewind().unwrap();
+        file
     }
 
-    files
+    fn file_names(files: &[TempFile]) -> Vec<&str> {
+        files
+            .iter()
+            .filter_map(|file| file.file_name.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn extracts_files() {
+        let archive = zip_archive(&[
+            ("index.html", Some(b"<h1>home</h1>")),
+            ("docs/", Some(b"")),
+            ("docs/a.css", Some(b"body {}")),
+        ]);
+
+        let files = extract_file(archive).unwrap();
+        assert_eq!(file_names(&files), ["index.html", "docs/a.css"]);
+    }
+
+    #[test]
+    fn skips_macos_metadata() {
+        let archive = zip_archive(&[
+            ("index.html", Some(b"x")),
+            ("./__MACOSX/._index.html", Some(b"x")),
+            ("./.DS_Store", Some(b"x")),
+            ("docs/.DS_Store", Some(b"x")),
+        ]);
+
+        let files = extract_file(archive).unwrap();
+        assert_eq!(file_names(&files), ["index.html"]);
+    }
+
+    #[test]
+    fn rejects_escaping_paths() {
+        for name in ["../evil.html", "/etc/passwd", "C:/evil.html", "a\u{1}.html"] {
+            let archive = zip_archive(&[(name, Some(b"x"))]);
+            assert!(extract_file(archive).is_err(), "{:?} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn rejects_symlinks() {
+        let archive = zip_archive(&[("index.html", Some(b"x")), ("link.html", None)]);
+        assert!(matches!(
+            extract_file(archive),
+            Err(AppError::InvalidArchive(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_too_many_entries() {
+        let names: Vec<String> = (0..=MAX_ENTRIES).map(|i| format!("{}.txt", i)).collect();
+        let entries: Vec<(&str, Option<&[u8]>)> = names
+            .iter()
+            .map(|name| (name.as_str(), Some(&b""[..])))
+            .collect();
+
+        assert!(matches!(
+            extract_file(zip_archive(&entries)),
+            Err(AppError::InvalidArchive(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_high_compression_ratios() {
+        let zeros = vec![0u8; 2 * MIN_RATIO_CHECKED_SIZE as usize];
+        let archive = zip_archive(&[("zeros.bin", Some(&zeros))]);
+
+        assert!(matches!(
+            extract_file(archive),
+            Err(AppError::InvalidArchive(_))
+        ));
+    }
 }
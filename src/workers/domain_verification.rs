@@ -0,0 +1,53 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use diesel::prelude::*;
+
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::handlers::domains::verify_domain;
+use crate::models::{Domain, DomainStatus};
+use crate::services::{dns::TxtResolver, dynamodb};
+
+/// Checks the pending custom domains every `interval` until the server stops.
+pub async fn run(
+    pool: DbPool,
+    resolver: Arc<dyn TxtResolver>,
+    dynamodb_client: dynamodb::Client,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        if let Err(e) = verify_pending_domains(&pool, resolver.as_ref(), &dynamodb_client).await {
+            println!("Error verifying domains: {}", e);
+        }
+    }
+}
+
+async fn verify_pending_domains(
+    pool: &DbPool,
+    resolver: &dyn TxtResolver,
+    dynamodb_client: &dynamodb::Client,
+) -> Result<(), AppError> {
+    use crate::schema::domains::dsl::{domains, status};
+
+    let mut conn = pool.get()?;
+
+    let pending_domains: Vec<Domain> = domains
+        .filter(status.eq(DomainStatus::Pending.as_str()))
+        .select(Domain::as_select())
+        .load::<Domain>(&mut conn)?;
+
+    for domain in pending_domains {
+        // A domain failing to resolve shouldn't hold back the others
+        match verify_domain(&mut conn, resolver, dynamodb_client, &domain).await {
+            Ok(true) => println!("Domain {} verified", domain.host),
+            Ok(false) => (),
+            Err(e) => println!("Error verifying domain {}: {}", domain.host, e),
+        }
+    }
+
+    Ok(())
+}
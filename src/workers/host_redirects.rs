@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use diesel::prelude::*;
+
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::handlers::deployments::release_routing;
+use crate::models::HostRedirect;
+use crate::services::dynamodb;
+
+/// Removes the expired redirects of renamed sites every `interval` until the
+/// server stops.
+pub async fn run(pool: DbPool, dynamodb_client: dynamodb::Client, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        if let Err(e) = remove_expired_redirects(&pool, &dynamodb_client).await {
+            println!("Error removing expired redirects: {}", e);
+        }
+    }
+}
+
+async fn remove_expired_redirects(
+    pool: &DbPool,
+    dynamodb_client: &dynamodb::Client,
+) -> Result<(), AppError> {
+    use crate::schema::host_redirects::dsl::{expires_at, host_redirects, id};
+
+    let mut conn = pool.get()?;
+
+    let expired_redirects: Vec<HostRedirect> = host_redirects
+        .filter(expires_at.le(Utc::now().naive_utc()))
+        .select(HostRedirect::as_select())
+        .load::<HostRedirect>(&mut conn)?;
+
+    for redirect in expired_redirects {
+        // The row is kept until the host is released, so a failure is retried
+        match release_routing(dynamodb_client, &redirect.host, &redirect.site_id).await {
+            Ok(()) => {
+                diesel::delete(host_redirects.filter(id.eq(redirect.id.clone())))
+                    .execute(&mut conn)?;
+                println!("Redirect of {} expired", redirect.host);
+            }
+            Err(e) => println!("Error releasing redirect {}: {}", redirect.host, e),
+        }
+    }
+
+    Ok(())
+}
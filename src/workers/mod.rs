@@ -0,0 +1,2 @@
+pub mod domain_verification;
+pub mod host_redirects;